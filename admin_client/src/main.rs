@@ -0,0 +1,142 @@
+use std::env;
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+use chatrs::admin::{
+    self, AdminRequest, AdminResponse, ClientInfo, ClientSelector, ServerStats,
+};
+
+const USAGE: &str = "\
+Usage: chatrs-admin [--socket PATH] COMMAND
+
+Commands:
+    clients           List connected clients
+    kick ID|NICK      Disconnect clients by id or nick
+    op ID|NICK        Make clients operators
+    deop ID|NICK      Take operator status from clients
+    notice TEXT...    Broadcast a notice to all clients
+    motd [TEXT...]    Set the message of the day, clear it if no text is given
+    reload            Reload the server configuration file
+    stats             Show server statistics";
+
+fn main() -> anyhow::Result<()> {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let socket = match args.iter().position(|arg| arg == "--socket") {
+        Some(index) if index + 1 < args.len() => {
+            let socket = PathBuf::from(args.remove(index + 1));
+            args.remove(index);
+            socket
+        }
+        Some(_) => usage(),
+        None => admin::default_socket_path(),
+    };
+    let request = parse_request(&args).unwrap_or_else(|| usage());
+
+    match send(&socket, &request)? {
+        AdminResponse::Clients { clients } => print_clients(&clients),
+        AdminResponse::Kicked { count } => println!("Kicked {} client(s)", count),
+        AdminResponse::OperatorChanged { count } => println!("Changed {} client(s)", count),
+        AdminResponse::Reloaded { restart_required } => {
+            println!("Configuration reloaded");
+            for setting in restart_required {
+                println!("Restart the server to apply changes to {}", setting);
+            }
+        }
+        AdminResponse::Stats { stats } => print_stats(&stats),
+        AdminResponse::Done => {}
+        AdminResponse::Error { message } => anyhow::bail!(message),
+    }
+    Ok(())
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn parse_request(args: &[String]) -> Option<AdminRequest> {
+    let (command, params) = args.split_first()?;
+    match (command.as_str(), params) {
+        ("clients", []) => Some(AdminRequest::ListClients),
+        ("kick", [client]) => Some(AdminRequest::Kick {
+            client: selector(client),
+        }),
+        ("op", [client]) => Some(AdminRequest::SetOperator {
+            client: selector(client),
+            operator: true,
+        }),
+        ("deop", [client]) => Some(AdminRequest::SetOperator {
+            client: selector(client),
+            operator: false,
+        }),
+        ("notice", text) if !text.is_empty() => Some(AdminRequest::Notice {
+            content: text.join(" "),
+        }),
+        ("motd", text) => Some(AdminRequest::SetMotd {
+            motd: text.join(" "),
+        }),
+        ("reload", []) => Some(AdminRequest::ReloadConfig),
+        ("stats", []) => Some(AdminRequest::Stats),
+        _ => None,
+    }
+}
+
+fn selector(client: &str) -> ClientSelector {
+    match client.parse() {
+        Ok(id) => ClientSelector::Id(id),
+        Err(_) => ClientSelector::Nick(client.to_owned()),
+    }
+}
+
+fn send(socket: &Path, request: &AdminRequest) -> anyhow::Result<AdminResponse> {
+    let mut stream = UnixStream::connect(socket)
+        .map_err(|e| anyhow::anyhow!("Could not connect to {}: {}", socket.display(), e))?;
+    stream.write_all(&request.serialize()?)?;
+    // The server reads the request until end of stream
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut data = Vec::new();
+    stream.read_to_end(&mut data)?;
+    Ok(AdminResponse::deserialize(&data)?)
+}
+
+fn print_clients(clients: &[ClientInfo]) {
+    println!(
+        "{:<6} {:<10} {:<22} {:<16} CONNECTED SINCE",
+        "ID", "TRANSPORT", "ENDPOINT", "NICK"
+    );
+    for client in clients {
+        let nick = if client.operator {
+            format!("@{}", client.nick)
+        } else {
+            client.nick.clone()
+        };
+        println!(
+            "{:<6} {:<10} {:<22} {:<16} {}",
+            client.id,
+            client.transport,
+            client.endpoint,
+            nick,
+            humantime::format_rfc3339_seconds(client.connected_since)
+        );
+    }
+}
+
+fn print_stats(stats: &ServerStats) {
+    let uptime = Duration::from_secs(stats.uptime.as_secs());
+    println!(
+        "uptime:                 {}",
+        humantime::format_duration(uptime)
+    );
+    println!("clients:                {}", stats.clients);
+    println!("messages received:      {}", stats.messages_received);
+    println!("messages sent:          {}", stats.messages_sent);
+    println!("bytes received:         {}", stats.bytes_received);
+    println!("bytes sent:             {}", stats.bytes_sent);
+    println!("deserialization errors: {}", stats.deserialization_errors);
+    println!("rate limit hits:        {}", stats.rate_limit_hits);
+}
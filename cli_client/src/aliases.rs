@@ -0,0 +1,46 @@
+use log::warn;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// File keeping the aliases defined with `/alias` as a TOML table of expansions by name
+pub struct AliasFile {
+    path: Option<PathBuf>,
+}
+
+impl AliasFile {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self { path }
+    }
+
+    /// Aliases saved earlier, none if the file doesn't exist or can't be read
+    pub fn load(&self) -> BTreeMap<String, String> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return BTreeMap::new(),
+        };
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return BTreeMap::new(),
+            Err(e) => {
+                warn!(path:% = path.display(), error:% = e; "Could not read aliases");
+                return BTreeMap::new();
+            }
+        };
+        toml::from_str(&contents).unwrap_or_else(|e| {
+            warn!(path:% = path.display(), error:% = e; "Could not parse aliases");
+            BTreeMap::new()
+        })
+    }
+
+    pub fn save(&self, aliases: &BTreeMap<String, String>) -> anyhow::Result<()> {
+        if let Some(ref path) = self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, toml::to_string(aliases)?)?;
+        }
+        Ok(())
+    }
+}
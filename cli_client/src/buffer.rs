@@ -0,0 +1,168 @@
+use chatrs::client::Target;
+use chatrs::{Member, Topic};
+use chrono::{DateTime, Local};
+
+use crate::scrollback::Scrollback;
+use crate::transcript::Transcript;
+use crate::Message;
+
+pub struct Line {
+    pub time: DateTime<Local>,
+    pub message: Message,
+}
+
+/// Messages of one conversation: the server itself, a channel or a private conversation
+pub struct Buffer {
+    pub target: Target,
+    pub messages: Scrollback<Line>,
+    /// Messages received while the buffer was not shown
+    pub unread: usize,
+    /// Unread messages mentioning the user, private messages always count
+    pub mentions: usize,
+    /// Users on the channel, empty for other buffers
+    pub members: Vec<Member>,
+    /// Topic of the channel, None for other buffers
+    pub topic: Option<Topic>,
+}
+
+impl Buffer {
+    fn new(target: Target) -> Self {
+        Self {
+            target,
+            messages: Scrollback::default(),
+            unread: 0,
+            mentions: 0,
+            members: Vec::new(),
+            topic: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        target_name(&self.target)
+    }
+}
+
+/// Name of the buffer for `target`
+pub fn target_name(target: &Target) -> &str {
+    match target {
+        Target::Server => "server",
+        Target::Channel(channel) => channel,
+        Target::Nick(nick) => nick,
+    }
+}
+
+/// Open buffers in the order they were opened, the server buffer is always first
+pub struct Buffers {
+    buffers: Vec<Buffer>,
+    current: usize,
+    transcript: Transcript,
+}
+
+impl Buffers {
+    pub fn new(transcript: Transcript) -> Self {
+        Self {
+            buffers: vec![Buffer::new(Target::Server)],
+            current: 0,
+            transcript,
+        }
+    }
+
+    pub fn transcript_mut(&mut self) -> &mut Transcript {
+        &mut self.transcript
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Buffer> {
+        self.buffers.iter()
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    pub fn current(&self) -> &Buffer {
+        &self.buffers[self.current]
+    }
+
+    pub fn current_mut(&mut self) -> &mut Buffer {
+        &mut self.buffers[self.current]
+    }
+
+    /// Switch to the buffer for `target`, opening it if needed
+    pub fn open(&mut self, target: Target) {
+        self.current = self.index_or_open(target);
+    }
+
+    /// Close the buffer for `target`, the server buffer can't be closed
+    pub fn close(&mut self, target: &Target) {
+        if let Some(index) = self.index(target).filter(|index| *index != 0) {
+            self.buffers.remove(index);
+            if self.current >= index {
+                self.current -= 1;
+            }
+        }
+    }
+
+    pub fn select(&mut self, index: usize) {
+        if let Some(buffer) = self.buffers.get_mut(index) {
+            buffer.unread = 0;
+            buffer.mentions = 0;
+            self.current = index;
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        self.select((self.current + 1) % self.buffers.len());
+    }
+
+    pub fn select_prev(&mut self) {
+        self.select((self.current + self.buffers.len() - 1) % self.buffers.len());
+    }
+
+    /// Add a message to the buffer for `target`, opening it in the background if needed
+    pub fn push(&mut self, target: Target, message: Message, mention: bool) {
+        let index = self.index_or_open(target);
+        let buffer = &mut self.buffers[index];
+        let line = Line {
+            time: Local::now(),
+            message,
+        };
+        self.transcript.write(buffer.name(), &line);
+        buffer.messages.push(line);
+        if index != self.current {
+            buffer.unread += 1;
+            if mention {
+                buffer.mentions += 1;
+            }
+        }
+    }
+
+    /// Add a message to the buffer for `target` if it is open
+    pub fn push_if_open(&mut self, target: &Target, message: Message) {
+        if self.index(target).is_some() {
+            self.push(target.clone(), message, false);
+        }
+    }
+
+    pub fn push_current(&mut self, message: Message) {
+        self.push(self.current().target.clone(), message, false);
+    }
+
+    pub fn get_mut(&mut self, target: &Target) -> Option<&mut Buffer> {
+        self.buffers
+            .iter_mut()
+            .find(|buffer| buffer.target == *target)
+    }
+
+    fn index(&self, target: &Target) -> Option<usize> {
+        self.buffers
+            .iter()
+            .position(|buffer| buffer.target == *target)
+    }
+
+    fn index_or_open(&mut self, target: Target) -> usize {
+        self.index(&target).unwrap_or_else(|| {
+            self.buffers.push(Buffer::new(target));
+            self.buffers.len() - 1
+        })
+    }
+}
@@ -0,0 +1,266 @@
+use chrono::format::{Item, StrftimeItems};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use termion::event::Key;
+
+use crate::theme::ThemeConfig;
+use crate::transcript::TranscriptConfig;
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Server prefilled in the `/connect` command
+    pub server: String,
+    /// Connect to `server` on startup
+    pub autoconnect: bool,
+    /// Reconnect when a shutting down server says when to come back
+    pub auto_reconnect: bool,
+    /// Nick set after connecting
+    pub nick: Option<String>,
+    /// Channels joined after connecting
+    pub autojoin: Vec<String>,
+    /// Words highlighting messages like mentions of the user's nick
+    pub highlight_words: Vec<String>,
+    /// strftime format for message timestamps, no timestamps are shown when unset
+    pub timestamp_format: Option<String>,
+    /// Show the help line at the top
+    pub show_help: bool,
+    /// Command reading a pasted snippet on stdin and printing its URL, which is sent instead of
+    /// the snippet. Uploading is not offered when empty.
+    pub snippet_command: Vec<String>,
+    /// Seconds without key presses after which the user is marked away until the next key press,
+    /// never when unset
+    pub auto_away_after: Option<u64>,
+    /// Key bindings by action name
+    pub keys: HashMap<String, String>,
+    pub theme: ThemeConfig,
+    pub notifications: NotificationConfig,
+    pub transcript: TranscriptConfig,
+    /// Where the configuration was read from
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+/// The `[notifications]` section: how to tell about mentions and private messages in the background
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationConfig {
+    /// Ring the terminal bell
+    pub bell: bool,
+    /// Command run with the title and message appended to it, not run when empty
+    pub command: Vec<String>,
+    /// Buffers that never notify, channels or nicks
+    pub muted: Vec<String>,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            bell: true,
+            command: Vec::new(),
+            muted: Vec::new(),
+        }
+    }
+}
+
+/// Actions that can be bound to keys in the `[keys]` section
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    NextBuffer,
+    PrevBuffer,
+    PageUp,
+    PageDown,
+    ScrollTop,
+    ScrollBottom,
+    HistoryPrev,
+    HistoryNext,
+    HistorySearch,
+    Complete,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            server: "127.0.0.1:3042".to_owned(),
+            autoconnect: false,
+            auto_reconnect: true,
+            nick: None,
+            autojoin: Vec::new(),
+            highlight_words: Vec::new(),
+            timestamp_format: None,
+            show_help: true,
+            snippet_command: Vec::new(),
+            auto_away_after: None,
+            keys: HashMap::new(),
+            theme: ThemeConfig::default(),
+            notifications: NotificationConfig::default(),
+            transcript: TranscriptConfig::default(),
+            path: None,
+        }
+    }
+}
+
+impl Config {
+    /// Where the configuration is read from unless a path is given on the command line
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("chatrs").join("cli_client.toml"))
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let mut config: Self = toml::from_str(&contents)?;
+        config.path = Some(path.to_owned());
+        if let Some(ref format) = config.timestamp_format {
+            if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                anyhow::bail!("Invalid timestamp_format: {}", format);
+            }
+        }
+        Ok(config)
+    }
+
+    /// Where aliases are kept: next to the configuration file, or in the default configuration
+    /// directory when there is none
+    pub fn aliases_path(&self) -> Option<PathBuf> {
+        match self.path {
+            Some(ref path) => Some(path.with_file_name("aliases.toml")),
+            None => Self::default_path().map(|path| path.with_file_name("aliases.toml")),
+        }
+    }
+
+    /// Default key bindings with the configured ones replacing them
+    pub fn key_bindings(&self) -> anyhow::Result<HashMap<Key, Action>> {
+        let mut bindings: HashMap<Key, Action> = [
+            (Key::Ctrl('n'), Action::NextBuffer),
+            (Key::Ctrl('p'), Action::PrevBuffer),
+            (Key::PageUp, Action::PageUp),
+            (Key::PageDown, Action::PageDown),
+            (Key::Up, Action::HistoryPrev),
+            (Key::Down, Action::HistoryNext),
+            (Key::Ctrl('r'), Action::HistorySearch),
+            (Key::Char('\t'), Action::Complete),
+        ]
+        .iter()
+        .copied()
+        .collect();
+        for (action, key) in self.keys.iter() {
+            let action = parse_action(action)
+                .ok_or_else(|| anyhow::anyhow!("Unknown action: {}", action))?;
+            let key =
+                parse_key(key).ok_or_else(|| anyhow::anyhow!("Invalid key binding: {}", key))?;
+            bindings.retain(|_, bound| *bound != action);
+            bindings.insert(key, action);
+        }
+        Ok(bindings)
+    }
+}
+
+fn parse_action(s: &str) -> Option<Action> {
+    let action = match s {
+        "next_buffer" => Action::NextBuffer,
+        "prev_buffer" => Action::PrevBuffer,
+        "page_up" => Action::PageUp,
+        "page_down" => Action::PageDown,
+        "scroll_top" => Action::ScrollTop,
+        "scroll_bottom" => Action::ScrollBottom,
+        "history_prev" => Action::HistoryPrev,
+        "history_next" => Action::HistoryNext,
+        "history_search" => Action::HistorySearch,
+        "complete" => Action::Complete,
+        _ => return None,
+    };
+    Some(action)
+}
+
+/// Parse a key like "ctrl-n", "alt-x", "pageup", "f5" or a single character
+fn parse_key(s: &str) -> Option<Key> {
+    let mut chars = s.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(Key::Char(c));
+    }
+    let lower = s.to_lowercase();
+    if let Some(c) = lower.strip_prefix("ctrl-").and_then(single_char) {
+        return Some(Key::Ctrl(c));
+    }
+    if let Some(c) = lower.strip_prefix("alt-").and_then(single_char) {
+        return Some(Key::Alt(c));
+    }
+    if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+        return Some(Key::F(n));
+    }
+    let key = match lower.as_str() {
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "tab" => Key::Char('\t'),
+        "backtab" => Key::BackTab,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "esc" => Key::Esc,
+        _ => return None,
+    };
+    Some(key)
+}
+
+fn single_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_characters() {
+        assert_eq!(parse_key("x"), Some(Key::Char('x')));
+        assert_eq!(parse_key("X"), Some(Key::Char('X')));
+        assert_eq!(parse_key("ä"), Some(Key::Char('ä')));
+    }
+
+    #[test]
+    fn parses_modifiers_ignoring_case() {
+        assert_eq!(parse_key("ctrl-n"), Some(Key::Ctrl('n')));
+        assert_eq!(parse_key("Ctrl-N"), Some(Key::Ctrl('n')));
+        assert_eq!(parse_key("alt-x"), Some(Key::Alt('x')));
+        assert_eq!(parse_key("ctrl-"), None);
+        assert_eq!(parse_key("ctrl-ab"), None);
+    }
+
+    #[test]
+    fn parses_named_and_function_keys() {
+        assert_eq!(parse_key("f5"), Some(Key::F(5)));
+        assert_eq!(parse_key("F12"), Some(Key::F(12)));
+        assert_eq!(parse_key("PageUp"), Some(Key::PageUp));
+        assert_eq!(parse_key("tab"), Some(Key::Char('\t')));
+        assert_eq!(parse_key("esc"), Some(Key::Esc));
+        assert_eq!(parse_key("fx"), None);
+        assert_eq!(parse_key("space"), None);
+    }
+
+    #[test]
+    fn configured_bindings_replace_the_defaults() {
+        let config: Config = toml::from_str("[keys]\nhistory_search = \"ctrl-s\"").unwrap();
+        let bindings = config.key_bindings().unwrap();
+        assert!(bindings.get(&Key::Ctrl('s')) == Some(&Action::HistorySearch));
+        assert!(!bindings.contains_key(&Key::Ctrl('r')));
+        assert!(bindings.get(&Key::Ctrl('n')) == Some(&Action::NextBuffer));
+    }
+
+    #[test]
+    fn invalid_bindings_are_errors() {
+        let config: Config = toml::from_str("[keys]\nfly = \"ctrl-f\"").unwrap();
+        assert!(config.key_bindings().is_err());
+        let config: Config = toml::from_str("[keys]\ncomplete = \"hyper-x\"").unwrap();
+        assert!(config.key_bindings().is_err());
+    }
+}
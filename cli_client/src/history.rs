@@ -0,0 +1,185 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use log::warn;
+
+/// Entries kept in memory and on disk, the oldest are dropped first
+const MAX_ENTRIES: usize = 1000;
+
+/// Input history, saved to the user's data directory after every entry
+pub struct History {
+    entries: Vec<String>,
+    /// Entry shown while browsing with Up/Down
+    index: Option<usize>,
+    path: Option<PathBuf>,
+}
+
+impl History {
+    /// Load the history file, starting with an empty history if it can't be read
+    pub fn load() -> Self {
+        let path = dirs::data_dir().map(|dir| dir.join("chatrs").join("history"));
+        let entries = match path {
+            Some(ref path) => match fs::read_to_string(path) {
+                Ok(contents) => contents.lines().map(unescape).collect(),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => {
+                    warn!(path:% = path.display(), error:% = e; "Could not read history");
+                    Vec::new()
+                }
+            },
+            None => Vec::new(),
+        };
+        Self {
+            entries,
+            index: None,
+            path,
+        }
+    }
+
+    /// Add an entry, moving it to the end if it is already in the history
+    pub fn push(&mut self, entry: &str) {
+        self.index = None;
+        if entry.trim().is_empty() {
+            return;
+        }
+        self.entries.retain(|e| e != entry);
+        self.entries.push(entry.to_owned());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.drain(..self.entries.len() - MAX_ENTRIES);
+        }
+        if let Err(e) = self.save() {
+            warn!(error:% = e; "Could not save history");
+        }
+    }
+
+    pub fn prev(&mut self) -> Option<&str> {
+        if !self.entries.is_empty() && self.index != Some(0) {
+            self.index = self.index.map(|x| x - 1).or(Some(self.entries.len() - 1));
+        }
+        self.current()
+    }
+
+    pub fn next(&mut self) -> Option<&str> {
+        self.index = if self.entries.is_empty() || self.index == Some(self.entries.len() - 1) {
+            None
+        } else {
+            self.index.map(|x| x + 1)
+        };
+        self.current()
+    }
+
+    /// Index of the newest entry older than `before` that contains `query`
+    pub fn search(&self, query: &str, before: usize) -> Option<usize> {
+        if query.is_empty() {
+            return None;
+        }
+        self.entries[..before.min(self.entries.len())]
+            .iter()
+            .rposition(|entry| entry.contains(query))
+    }
+
+    pub fn get(&self, index: usize) -> &str {
+        &self.entries[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn current(&self) -> Option<&str> {
+        self.index.map(|index| self.entries[index].as_str())
+    }
+
+    fn save(&self) -> io::Result<()> {
+        if let Some(ref path) = self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let entries: Vec<String> = self.entries.iter().map(|entry| escape(entry)).collect();
+            let mut contents = entries.join("\n");
+            contents.push('\n');
+            fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+}
+
+/// Escape newlines in multiline entries to keep one entry per line in the file
+fn escape(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(line: &str) -> String {
+    let mut entry = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                entry.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                entry.push('\\');
+                chars.next();
+            }
+            (c, _) => entry.push(c),
+        }
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(entries: &[&str]) -> History {
+        History {
+            entries: entries.iter().map(|entry| entry.to_string()).collect(),
+            index: None,
+            path: None,
+        }
+    }
+
+    #[test]
+    fn escapes_newlines_and_backslashes() {
+        assert_eq!(escape("one\ntwo"), r"one\ntwo");
+        assert_eq!(escape(r"C:\new"), r"C:\\new");
+        for entry in ["plain", "one\ntwo\n", r"C:\new", "\\\n\\n", "trailing\\"] {
+            assert_eq!(unescape(&escape(entry)), entry);
+            assert!(!escape(entry).contains('\n'));
+        }
+    }
+
+    #[test]
+    fn unescape_keeps_unknown_escapes() {
+        assert_eq!(unescape(r"a\tb\"), r"a\tb\");
+    }
+
+    #[test]
+    fn push_moves_duplicates_to_the_end() {
+        let mut history = history(&["a", "b", "c"]);
+        history.push("a");
+        history.push(" ");
+        assert_eq!(history.entries, ["b", "c", "a"]);
+    }
+
+    #[test]
+    fn browses_entries() {
+        let mut history = history(&["a", "b"]);
+        assert_eq!(history.prev(), Some("b"));
+        assert_eq!(history.prev(), Some("a"));
+        assert_eq!(history.prev(), Some("a"));
+        assert_eq!(history.next(), Some("b"));
+        assert_eq!(history.next(), None);
+    }
+
+    #[test]
+    fn searches_older_entries() {
+        let history = history(&["git push", "ls", "git pull", "make"]);
+        assert_eq!(history.search("git", history.len()), Some(2));
+        assert_eq!(history.search("git", 2), Some(0));
+        assert_eq!(history.search("git", 0), None);
+        assert_eq!(history.search("", history.len()), None);
+    }
+}
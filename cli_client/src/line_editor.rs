@@ -0,0 +1,363 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Text input with a cursor, moving over grapheme clusters rather than chars. Newlines split the
+/// text in lines.
+#[derive(Default)]
+pub struct LineEditor {
+    text: String,
+    /// Byte index of the cursor, always on a grapheme boundary
+    cursor: usize,
+    /// Display column of the first visible grapheme when the text does not fit
+    scroll: usize,
+    /// First visible line when the lines do not fit
+    top: usize,
+}
+
+impl LineEditor {
+    pub fn new(text: impl Into<String>) -> Self {
+        let mut editor = Self::default();
+        editor.set(text);
+        editor
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Replace the text and move the cursor to its end
+    pub fn set(&mut self, text: impl Into<String>) {
+        self.text = text.into();
+        self.cursor = self.text.len();
+    }
+
+    /// Replace the text and put the cursor at byte index `cursor`
+    pub fn set_with_cursor(&mut self, text: impl Into<String>, cursor: usize) {
+        self.text = text.into();
+        self.cursor = cursor.min(self.text.len());
+    }
+
+    /// Clear the editor and return its text
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        self.scroll = 0;
+        self.top = 0;
+        std::mem::take(&mut self.text)
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+        // A combining char joins the grapheme before the cursor
+        self.cursor = self.next_boundary(self.prev_boundary(self.cursor));
+    }
+
+    pub fn insert_str(&mut self, s: &str) {
+        self.text.insert_str(self.cursor, s);
+        self.cursor += s.len();
+        self.cursor = self.next_boundary(self.prev_boundary(self.cursor));
+    }
+
+    pub fn backspace(&mut self) {
+        let start = self.prev_boundary(self.cursor);
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    pub fn delete(&mut self) {
+        let end = self.next_boundary(self.cursor);
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = self.prev_boundary(self.cursor);
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = self.next_boundary(self.cursor);
+    }
+
+    /// Move to the start of the line
+    pub fn move_home(&mut self) {
+        self.cursor = self.line_start(self.cursor);
+    }
+
+    /// Move to the end of the line
+    pub fn move_end(&mut self) {
+        self.cursor = self.line_end(self.cursor);
+    }
+
+    /// Move to the same column on the previous line, returns false on the first line
+    pub fn move_up(&mut self) -> bool {
+        let start = self.line_start(self.cursor);
+        if start == 0 {
+            return false;
+        }
+        let column = self.text[start..self.cursor].width();
+        self.cursor = self.column_index(self.line_start(start - 1), start - 1, column);
+        true
+    }
+
+    /// Move to the same column on the next line, returns false on the last line
+    pub fn move_down(&mut self) -> bool {
+        let end = self.line_end(self.cursor);
+        if end == self.text.len() {
+            return false;
+        }
+        let column = self.text[self.line_start(self.cursor)..self.cursor].width();
+        self.cursor = self.column_index(end + 1, self.line_end(end + 1), column);
+        true
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.text.matches('\n').count() + 1
+    }
+
+    pub fn word_left(&mut self) {
+        self.cursor = self.prev_word(self.cursor);
+    }
+
+    pub fn word_right(&mut self) {
+        let rest = &self.text[self.cursor..];
+        let word_start = rest.len() - rest.trim_start().len();
+        let word_end = rest[word_start..]
+            .find(char::is_whitespace)
+            .map(|end| word_start + end)
+            .unwrap_or_else(|| rest.len());
+        self.cursor += word_end;
+    }
+
+    /// Delete the word before the cursor
+    pub fn kill_word(&mut self) {
+        let start = self.prev_word(self.cursor);
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Delete everything before the cursor on its line
+    pub fn kill_to_start(&mut self) {
+        let start = self.line_start(self.cursor);
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Delete everything after the cursor on its line
+    pub fn kill_to_end(&mut self) {
+        let end = self.line_end(self.cursor);
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    /// The lines fitting in `width` columns and `height` rows, scrolled to keep the cursor in
+    /// view, and the column and row of the cursor within them
+    pub fn view(&mut self, width: usize, height: usize) -> (Vec<&str>, usize, usize) {
+        let line_start = self.line_start(self.cursor);
+        let cursor_column = self.text[line_start..self.cursor].width();
+        let cursor_row = self.text[..self.cursor].matches('\n').count();
+
+        // Don't leave empty space on the right or below after the text gets shorter, the extra
+        // column is for the cursor at the end
+        let text_width = self
+            .text
+            .split('\n')
+            .map(|line| line.width())
+            .max()
+            .unwrap_or(0)
+            + 1;
+        self.scroll = self.scroll.min(text_width.saturating_sub(width));
+        if cursor_column < self.scroll {
+            self.scroll = cursor_column;
+        } else if width > 0 && cursor_column >= self.scroll + width {
+            self.scroll = cursor_column + 1 - width;
+        }
+        self.top = self.top.min(self.line_count().saturating_sub(height));
+        if cursor_row < self.top {
+            self.top = cursor_row;
+        } else if height > 0 && cursor_row >= self.top + height {
+            self.top = cursor_row + 1 - height;
+        }
+
+        let scroll = self.scroll;
+        let cursor_line = self.line_end(self.cursor);
+        let (_, start_column) = columns(&self.text[line_start..cursor_line], scroll, width);
+        let lines = self
+            .text
+            .split('\n')
+            .skip(self.top)
+            .take(height)
+            .map(|line| columns(line, scroll, width).0)
+            .collect();
+        (
+            lines,
+            cursor_column.saturating_sub(start_column),
+            cursor_row - self.top,
+        )
+    }
+
+    fn line_start(&self, index: usize) -> usize {
+        self.text[..index].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    fn line_end(&self, index: usize) -> usize {
+        self.text[index..]
+            .find('\n')
+            .map_or(self.text.len(), |i| index + i)
+    }
+
+    /// Byte index of the grapheme at display `column` of the line between `start` and `end`, or
+    /// the end of the line if it is shorter
+    fn column_index(&self, start: usize, end: usize, column: usize) -> usize {
+        let mut width = 0;
+        for (index, grapheme) in self.text[start..end].grapheme_indices(true) {
+            width += grapheme.width();
+            if width > column {
+                return start + index;
+            }
+        }
+        end
+    }
+
+    fn prev_boundary(&self, index: usize) -> usize {
+        self.text[..index]
+            .grapheme_indices(true)
+            .next_back()
+            .map(|(i, _)| i)
+            .unwrap_or(0)
+    }
+
+    fn next_boundary(&self, index: usize) -> usize {
+        self.text[index..]
+            .graphemes(true)
+            .next()
+            .map(|grapheme| index + grapheme.len())
+            .unwrap_or(index)
+    }
+
+    fn prev_word(&self, index: usize) -> usize {
+        let before = self.text[..index].trim_end();
+        before
+            .rfind(char::is_whitespace)
+            .map(|i| i + before[i..].chars().next().map_or(1, char::len_utf8))
+            .unwrap_or(0)
+    }
+}
+
+/// The part of `line` from display column `start` fitting in `width` columns and the column it
+/// starts at, which is after `start` if a wide grapheme spans it
+fn columns(line: &str, start: usize, width: usize) -> (&str, usize) {
+    let mut from = None;
+    let mut to = line.len();
+    let mut column = 0;
+    for (index, grapheme) in line.grapheme_indices(true) {
+        if from.is_none() && column >= start {
+            from = Some((index, column));
+        }
+        column += grapheme.width();
+        if from.is_some() && column > start + width {
+            to = index;
+            break;
+        }
+    }
+    let (from, from_column) = from.unwrap_or((line.len(), column));
+    (&line[from..to.max(from)], from_column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_over_graphemes() {
+        let mut editor = LineEditor::new("ne");
+        // A combining acute accent joins the e before it
+        editor.insert('\u{301}');
+        assert_eq!(editor.text(), "ne\u{301}");
+        editor.move_left();
+        assert_eq!(editor.cursor(), 1);
+        editor.move_right();
+        assert_eq!(editor.cursor(), 4);
+        editor.backspace();
+        assert_eq!(editor.text(), "n");
+    }
+
+    #[test]
+    fn deletes_at_the_cursor() {
+        let mut editor = LineEditor::new("abc");
+        editor.move_home();
+        editor.delete();
+        assert_eq!(editor.text(), "bc");
+        editor.move_end();
+        editor.delete();
+        assert_eq!(editor.text(), "bc");
+        assert_eq!(editor.take(), "bc");
+        assert_eq!((editor.text(), editor.cursor()), ("", 0));
+    }
+
+    #[test]
+    fn moves_and_kills_by_word() {
+        let mut editor = LineEditor::new("hello  big world");
+        editor.word_left();
+        assert_eq!(editor.cursor(), 11);
+        editor.word_left();
+        assert_eq!(editor.cursor(), 7);
+        editor.word_right();
+        assert_eq!(editor.cursor(), 10);
+        editor.move_end();
+        editor.kill_word();
+        assert_eq!(editor.text(), "hello  big ");
+        editor.kill_word();
+        assert_eq!(editor.text(), "hello  ");
+    }
+
+    #[test]
+    fn kills_to_the_line_start_and_end() {
+        let mut editor = LineEditor::new("");
+        editor.set_with_cursor("one\nabc def\ntwo", 7);
+        editor.kill_to_end();
+        assert_eq!(editor.text(), "one\nabc\ntwo");
+        editor.set_with_cursor("one\nabc def\ntwo", 8);
+        editor.kill_to_start();
+        assert_eq!(editor.text(), "one\ndef\ntwo");
+        assert_eq!(editor.cursor(), 4);
+    }
+
+    #[test]
+    fn moves_between_lines_keeping_the_column() {
+        let mut editor = LineEditor::new("ab\ncdef\ng");
+        assert_eq!(editor.line_count(), 3);
+        assert!(editor.move_up());
+        assert_eq!(editor.cursor(), 4);
+        assert!(editor.move_up());
+        assert_eq!(editor.cursor(), 1);
+        assert!(!editor.move_up());
+        assert!(editor.move_down());
+        assert!(editor.move_down());
+        assert_eq!(editor.cursor(), 9);
+        assert!(!editor.move_down());
+        editor.move_home();
+        assert_eq!(editor.cursor(), 8);
+    }
+
+    #[test]
+    fn view_scrolls_to_the_cursor() {
+        let mut editor = LineEditor::new("abcdefghij");
+        assert_eq!(editor.view(5, 1), (vec!["ghij"], 4, 0));
+        editor.move_home();
+        assert_eq!(editor.view(5, 1), (vec!["abcde"], 0, 0));
+
+        let mut editor = LineEditor::new("a\nb\nc");
+        assert_eq!(editor.view(10, 2), (vec!["b", "c"], 1, 1));
+        assert!(editor.move_up() && editor.move_up());
+        assert_eq!(editor.view(10, 2), (vec!["a", "b"], 1, 0));
+    }
+
+    #[test]
+    fn view_does_not_split_wide_graphemes() {
+        let mut editor = LineEditor::new("a日本語");
+        // The cursor after 語 is at column 7, so the view starts at column 4 in the middle of 本
+        assert_eq!(editor.view(4, 1), (vec!["語"], 2, 0));
+    }
+}
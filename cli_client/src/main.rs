@@ -1,52 +1,160 @@
-use anyhow;
 use message_io::network::{NetEvent, ToRemoteAddr, Transport};
 use message_io::node::{self, NodeEvent, NodeHandler};
 use std::thread;
 
-use chatrs::client::{ChatClient, ChatClientCommon, ChatError, ChatResult, ChatUserInterface};
+use chatrs::client::{
+    format_idle, ChatClient, ChatClientCommon, ChatError, ChatResult, ChatUserInterface,
+    CommandRegistry, Target,
+};
+use chatrs::command::{Arg, ArgType, CommandSpec};
+use chatrs::completion::Completer;
+use chatrs::highlight;
+use chatrs::logging::{self, LogFormat};
+use chatrs::{
+    is_channel_name, ChannelInfo, ClientMessage, Member, MessageKind, Topic, UserInfo, DEFAULT_NICK,
+};
+use chrono::{DateTime, Local};
+use log::{debug, error, info, warn};
+use serde::Serialize;
 
-use std::io;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
 use std::sync::mpsc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use unicode_width::UnicodeWidthStr;
 
 use termion::{
-    event::Key, input::MouseTerminal, input::TermRead, raw::IntoRawMode, screen::AlternateScreen,
+    event::{Event as InputEvent, Key, MouseButton, MouseEvent},
+    input::MouseTerminal,
+    input::TermRead,
+    raw::IntoRawMode,
+    screen::AlternateScreen,
 };
 use tui::{
     backend::{Backend, TermionBackend},
     layout::{Constraint, Direction, Layout},
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Span, Spans, Text},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{Block, Borders, List, ListItem, Paragraph, Tabs},
     Terminal,
 };
 
-use unicode_width::UnicodeWidthStr;
+mod aliases;
+mod buffer;
+mod config;
+mod history;
+mod line_editor;
+mod message_view;
+mod notify;
+mod pipe;
+mod scrollback;
+mod snippet;
+mod theme;
+mod transcript;
+
+use aliases::AliasFile;
+use buffer::{target_name, Buffers, Line};
+use config::{Action, Config};
+use history::History;
+use line_editor::LineEditor;
+use message_view::MessageView;
+use notify::Notifier;
+use pipe::PipeOptions;
+use theme::Theme;
+use transcript::Transcript;
+
+/// Lines scrolled per mouse wheel step
+const WHEEL_SCROLL_MESSAGES: usize = 3;
+/// Spaces inserted for a pasted tab
+const TAB_WIDTH: usize = 4;
+/// Lines of a multiline input shown at once
+const MAX_INPUT_ROWS: usize = 5;
+/// Width of the channel member list, including borders
+const NICK_LIST_WIDTH: u16 = 20;
+/// How often to measure latency to the server
+const PING_INTERVAL: Duration = Duration::from_secs(5);
+/// Time between the lines of a paste sent line by line, the pipe mode's default rate
+const PASTE_LINE_INTERVAL: Duration = Duration::from_millis(500);
+/// Away reason set by `auto_away_after`
+const AUTO_AWAY_REASON: &str = "Away from the keyboard";
+const TRANSPORT: Transport = Transport::FramedTcp;
+
+// Modified keys termion does not recognize and reports as unsupported escape sequences
+const CTRL_LEFT: &[u8] = b"\x1b[1;5D";
+const CTRL_RIGHT: &[u8] = b"\x1b[1;5C";
+const CTRL_HOME: &[u8] = b"\x1b[1;5H";
+const CTRL_END: &[u8] = b"\x1b[1;5F";
+// Sent by terminals around pasted text when bracketed paste is turned on
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+const BRACKETED_PASTE_ON: &str = "\x1b[?2004h";
+const BRACKETED_PASTE_OFF: &str = "\x1b[?2004l";
+// Sent by terminals supporting focus reporting when it is turned on
+const FOCUS_IN: &[u8] = b"\x1b[I";
+const FOCUS_OUT: &[u8] = b"\x1b[O";
+const FOCUS_REPORTING_ON: &str = "\x1b[?1004h";
+const FOCUS_REPORTING_OFF: &str = "\x1b[?1004l";
 
 pub enum Event<I> {
     Connect(String),
+    /// Connect if no connect or disconnect happened since the reconnect with this generation was
+    /// scheduled
+    Reconnect(String, u64),
     Disconnect,
     Connected(String),
     Disconnected,
     RecvMessage(Vec<u8>),
-    Enter,
     Nope,
     Input(I),
     Tick,
+    /// A snippet upload finished with the snippet's URL for the target or an error
+    SnippetUploaded(Target, Result<String, String>),
 }
 
 pub struct Events {
-    tx: mpsc::Sender<Event<Key>>,
-    rx: mpsc::Receiver<Event<Key>>,
+    tx: mpsc::Sender<Event<InputEvent>>,
+    rx: mpsc::Receiver<Event<InputEvent>>,
     _input_handle: thread::JoinHandle<()>,
     _tick_handle: thread::JoinHandle<()>,
 }
 
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
 enum Message {
-    Chat { nick: String, content: String },
-    ChangeNick { nick: String },
-    Status { content: String },
-    Error { content: String },
+    Chat {
+        nick: String,
+        // The message's own tag is also called kind
+        #[serde(rename = "message_kind")]
+        kind: MessageKind,
+        content: String,
+        /// Mentions the user or a highlight word
+        highlight: bool,
+    },
+    ChangeNick {
+        nick: String,
+    },
+    Notice {
+        content: String,
+    },
+    Status {
+        content: String,
+    },
+    Error {
+        content: String,
+    },
+}
+
+/// State of a Ctrl-R reverse history search
+struct HistorySearch {
+    query: String,
+    /// Index of the history entry matching the query
+    found: Option<usize>,
+    /// Input before the search, restored if the search is cancelled
+    original: String,
 }
 
 enum ChatSignal {
@@ -54,133 +162,774 @@ enum ChatSignal {
 }
 
 struct App {
+    config: Config,
+    theme: Theme,
+    key_bindings: HashMap<Key, Action>,
     running: bool,
     nick: Option<String>,
-    input: String,
-    history: Vec<String>,
-    history_index: Option<usize>,
-    messages: Vec<Message>,
+    /// Away reason, None when not away
+    away: Option<String>,
+    /// Set away by `auto_away_after`, back on the next key press
+    auto_away: bool,
+    /// When a key was last pressed
+    last_input: Instant,
+    input: LineEditor,
+    history: History,
+    search: Option<HistorySearch>,
+    completer: Completer,
+    commands: CommandRegistry,
+    alias_file: AliasFile,
+    buffers: Buffers,
+    /// Height of the message view when it was last rendered
+    message_height: usize,
+    /// Width of the message view when it was last rendered
+    message_width: usize,
+    /// Messages that fit completely in the message view when it was last rendered, at least one
+    message_page: usize,
+    server: Option<String>,
+    /// Bumped by connecting and disconnecting, which cancels a scheduled reconnect
+    reconnect_generation: u64,
+    /// Round trip time of the latest ping
+    latency: Option<Duration>,
+    /// Token and send time of the ping waiting for a pong
+    ping: Option<(u64, Instant)>,
+    next_ping_token: u64,
+    notifiers: Vec<Box<dyn Notifier>>,
+    /// Buffers that never notify
+    muted: HashSet<String>,
+    /// Whether the terminal has focus, terminals without focus reporting always do
+    focused: bool,
+    /// Text pasted so far, between the start and end of a bracketed paste
+    paste: Option<String>,
+    /// Multiline paste waiting for the user to choose how to send it
+    pending_paste: Option<String>,
+    /// Lines of a paste sent line by line still to go, one is sent every `PASTE_LINE_INTERVAL`
+    paste_lines: VecDeque<(Target, String)>,
+    /// When the next paste line may be sent
+    next_paste_line: Instant,
     handler: Option<NodeHandler<ChatSignal>>,
     events: Events,
 }
 
-impl Default for App {
-    fn default() -> Self {
-        Self {
+impl App {
+    fn new(config: Config) -> anyhow::Result<Self> {
+        let theme = Theme::from_config(&config.theme)?;
+        let key_bindings = config.key_bindings()?;
+        let input = if config.autoconnect {
+            LineEditor::default()
+        } else {
+            LineEditor::new(format!("/connect {}", config.server))
+        };
+        let notifiers = notify::notifiers(&config.notifications);
+        let muted = config.notifications.muted.iter().cloned().collect();
+        let buffers = Buffers::new(Transcript::new(&config.transcript));
+        let mut commands = CommandRegistry::default();
+        commands.register(UI_COMMANDS);
+        let alias_file = AliasFile::new(config.aliases_path());
+        for (name, expansion) in alias_file.load() {
+            if let Err(e) = commands.set_alias(&name, &expansion) {
+                warn!(alias = name.as_str(), error = e.as_str(); "Ignoring saved alias");
+            }
+        }
+        let mut completer = Completer::default();
+        completer.set_commands(commands.names());
+        let events = Events::new();
+        if config.autoconnect {
+            events.tx.send(Event::Connect(config.server.clone()))?;
+        }
+        Ok(Self {
+            config,
+            theme,
+            key_bindings,
             running: true,
             nick: None,
-            input: "/connect 127.0.0.1:3042".to_owned(),
-            history: Vec::new(),
-            history_index: None,
-            messages: Vec::new(),
+            away: None,
+            auto_away: false,
+            last_input: Instant::now(),
+            input,
+            history: History::load(),
+            search: None,
+            completer,
+            commands,
+            alias_file,
+            buffers,
+            message_height: 0,
+            message_width: 0,
+            message_page: 1,
+            server: None,
+            reconnect_generation: 0,
+            latency: None,
+            ping: None,
+            next_ping_token: 0,
+            notifiers,
+            muted,
+            focused: true,
+            paste: None,
+            pending_paste: None,
+            paste_lines: VecDeque::new(),
+            next_paste_line: Instant::now(),
             handler: None,
-            events: Events::new(),
-        }
+            events,
+        })
     }
 }
 
+/// Commands of the terminal UI, registered with the common ones
+const UI_COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "/close",
+        args: &[],
+        min_args: 0,
+        help: "Close the current buffer, parting channels",
+    },
+    CommandSpec {
+        name: "/log",
+        args: &[Arg {
+            name: "on|off",
+            kind: ArgType::Word,
+        }],
+        min_args: 0,
+        help: "Turn transcripts on or off, or show where they are written",
+    },
+    CommandSpec {
+        name: "/mute",
+        args: &[Arg {
+            name: "buffer",
+            kind: ArgType::Word,
+        }],
+        min_args: 0,
+        help: "Stop notifications from a buffer, the current one by default",
+    },
+    CommandSpec {
+        name: "/unmute",
+        args: &[Arg {
+            name: "buffer",
+            kind: ArgType::Word,
+        }],
+        min_args: 0,
+        help: "Notify about a muted buffer again, the current one by default",
+    },
+];
+
+/// Log file path, logging is disabled when unset since the terminal is taken by the UI
+const LOG_FILE_ENV: &str = "CHATRS_LOG_FILE";
+/// Messages sent per second in pipe mode unless `--rate` is given
+const DEFAULT_PIPE_RATE: f64 = 2.0;
+
+const USAGE: &str = "\
+Usage: cli_client [CONFIG]
+       cli_client --pipe [OPTIONS] [CONFIG]
+
+CONFIG defaults to chatrs/cli_client.toml in the user's config directory.
+
+Pipe mode sends each line of stdin as a message instead of starting the terminal UI:
+    --server ADDRESS    Server to connect to, defaults to the configured server
+    --nick NICK         Nick to use, defaults to the configured nick
+    --join CHANNEL      Join CHANNEL and send the lines there instead of the server-wide chat
+    --rate N            Send at most N messages per second, defaults to 2
+    --tail              Print received messages and keep running after stdin ends
+    --json              Print received messages as JSON Lines instead of plain text";
+
 fn main() -> anyhow::Result<()> {
+    init_logging()?;
+
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let pipe = take_flag(&mut args, "--pipe");
+    let server = take_option(&mut args, "--server");
+    let nick = take_option(&mut args, "--nick");
+    let channel = take_option(&mut args, "--join");
+    let interval = take_option(&mut args, "--rate").map(|rate| {
+        rate.parse()
+            .ok()
+            .and_then(pipe::send_interval)
+            .unwrap_or_else(|| usage())
+    });
+    let tail = take_flag(&mut args, "--tail");
+    let json = take_flag(&mut args, "--json");
+    let pipe_options_given = server.is_some()
+        || nick.is_some()
+        || channel.is_some()
+        || interval.is_some()
+        || tail
+        || json;
+    let config_path = match args.as_slice() {
+        [] => None,
+        [path] if !path.starts_with("--") => Some(PathBuf::from(path)),
+        _ => usage(),
+    };
+    if pipe_options_given && !pipe {
+        usage();
+    }
+
+    // Load the configuration before taking over the terminal so errors are readable
+    let config = match config_path {
+        Some(path) => Config::load(&path)?,
+        None => match Config::default_path().filter(|path| path.exists()) {
+            Some(path) => Config::load(&path)?,
+            None => Config::default(),
+        },
+    };
+
+    if pipe {
+        if let Some(ref channel) = channel {
+            if !is_channel_name(channel) {
+                anyhow::bail!("Invalid channel name: {}", channel);
+            }
+        }
+        return pipe::run(PipeOptions {
+            server: server.unwrap_or(config.server),
+            nick: nick.or(config.nick),
+            channel,
+            interval: interval.unwrap_or_else(|| Duration::from_secs_f64(1.0 / DEFAULT_PIPE_RATE)),
+            tail,
+            json,
+        });
+    }
+
+    let mut app = App::new(config)?;
+
     let stdout = io::stdout().into_raw_mode().expect("Error opening stdout");
     let stdout = MouseTerminal::from(stdout);
     let stdout = AlternateScreen::from(stdout);
     let backend = TermionBackend::new(stdout);
     let mut terminal = Terminal::new(backend).expect("Error initializing terminal");
-
-    let mut app = App::default();
+    write!(
+        terminal.backend_mut(),
+        "{}{}",
+        FOCUS_REPORTING_ON,
+        BRACKETED_PASTE_ON
+    )?;
 
     while app.running {
         app.render_ui(&mut terminal)?;
         app.handle_events()?;
     }
 
+    write!(
+        terminal.backend_mut(),
+        "{}{}",
+        FOCUS_REPORTING_OFF,
+        BRACKETED_PASTE_OFF
+    )?;
+
+    Ok(())
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+/// Remove `flag` from `args`, returns whether it was there
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    match args.iter().position(|arg| arg == flag) {
+        Some(index) => {
+            args.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Remove `option` and the value after it from `args`, returns the value
+fn take_option(args: &mut Vec<String>, option: &str) -> Option<String> {
+    match args.iter().position(|arg| arg == option) {
+        Some(index) if index + 1 < args.len() => {
+            let value = args.remove(index + 1);
+            args.remove(index);
+            Some(value)
+        }
+        Some(_) => usage(),
+        None => None,
+    }
+}
+
+/// Status line about the topic of `channel`, with who set it and when
+fn describe_topic(channel: &str, topic: Option<&Topic>) -> String {
+    match topic {
+        Some(topic) => {
+            let set_at: DateTime<Local> = topic.set_at.into();
+            format!(
+                "Topic of {}: {} (set by {} on {})",
+                channel,
+                topic.text,
+                topic.set_by,
+                set_at.format("%Y-%m-%d %H:%M")
+            )
+        }
+        None => format!("No topic on {}", channel),
+    }
+}
+
+/// Rows under a header with the columns aligned, the last column is not padded
+fn format_table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|title| title.width()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.width());
+        }
+    }
+    let header: Vec<String> = header.iter().map(|title| title.to_string()).collect();
+    std::iter::once(&header)
+        .chain(rows)
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{}{}", cell, " ".repeat(width - cell.width())))
+                .collect();
+            cells.join("  ").trim_end().to_owned()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn init_logging() -> anyhow::Result<()> {
+    if let Ok(path) = env::var(LOG_FILE_ENV) {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        logging::builder("info", LogFormat::from_env()?)
+            .target(logging::Target::Pipe(Box::new(file)))
+            .try_init()?;
+    }
     Ok(())
 }
 
 impl App {
-    fn connected(&mut self, address: String) {
+    fn connected(&mut self, address: String) -> ChatResult<()> {
         self.handle_status(format!("Connected to {}", address));
+        if let Some(nick) = self.config.nick.clone() {
+            self.handle_command("/nick".to_owned(), vec![nick])?;
+        }
+        for channel in self.config.autojoin.clone() {
+            self.handle_command("/join".to_owned(), vec![channel])?;
+        }
+        // The server forgets the away status on disconnecting
+        if let Some(reason) = self.away.clone() {
+            let auto_away = self.auto_away;
+            self.handle_command("/away".to_owned(), vec![reason])?;
+            self.auto_away = auto_away;
+        }
+        Ok(())
     }
     fn disconnected(&mut self) {
+        self.latency = None;
+        self.ping = None;
         self.handle_status("Disconnected");
     }
+    fn tick(&mut self) -> ChatResult<()> {
+        if !self.paste_lines.is_empty() && Instant::now() >= self.next_paste_line {
+            self.next_paste_line = Instant::now() + PASTE_LINE_INTERVAL;
+            if let Some((target, line)) = self.paste_lines.pop_front() {
+                if let Err(e) = self.send_message_to(target, MessageKind::Normal, line) {
+                    // The rest would fail the same way
+                    self.paste_lines.clear();
+                    return Err(e);
+                }
+            }
+        }
+        let ping_due = match self.ping {
+            Some((_, sent)) => sent.elapsed() >= PING_INTERVAL,
+            None => true,
+        };
+        if self.is_connected() && ping_due {
+            let token = self.next_ping_token;
+            self.next_ping_token += 1;
+            self.ping = Some((token, Instant::now()));
+            self.send(ClientMessage::Ping { token })?;
+        }
+        if let Some(after) = self.config.auto_away_after {
+            let idle = self.last_input.elapsed() >= Duration::from_secs(after);
+            if self.is_connected() && self.away.is_none() && idle {
+                self.handle_command("/away".to_owned(), vec![AUTO_AWAY_REASON.to_owned()])?;
+                self.auto_away = true;
+            }
+        }
+        Ok(())
+    }
     fn handle_status(&mut self, content: impl ToString) {
-        self.messages.push(Message::Status {
+        self.buffers.push_current(Message::Status {
             content: content.to_string(),
         });
     }
     fn handle_error(&mut self, content: impl ToString) {
-        self.messages.push(Message::Error {
-            content: content.to_string(),
-        });
+        let content = content.to_string();
+        debug!(error = content.as_str(); "Showing error");
+        self.buffers.push_current(Message::Error { content });
+    }
+    fn own_nick(&self) -> &str {
+        self.nick.as_deref().unwrap_or(DEFAULT_NICK)
+    }
+    /// Input title asking how to send a multiline paste
+    fn paste_prompt(&self) -> String {
+        let lines = self
+            .pending_paste
+            .as_deref()
+            .map_or(0, |paste| paste.lines().count());
+        let snippet = if self.config.snippet_command.is_empty() {
+            ""
+        } else {
+            ", s: upload as snippet"
+        };
+        format!(
+            "Pasted {} lines - m: send as one message, l: send line by line{}, e: edit, Esc: cancel",
+            lines, snippet
+        )
+    }
+    /// Whether a message from `nick` mentions the user or any of the highlight words
+    fn is_highlight(&self, nick: &str, content: &str) -> bool {
+        // The default nick is not worth highlighting, an empty nick never matches
+        let own_nick = self.nick.as_deref().unwrap_or("");
+        nick != self.own_nick()
+            && highlight::is_highlight(content, own_nick, &self.config.highlight_words)
+    }
+    fn nick_style(&self, nick: &str) -> Style {
+        Style::default().fg(self.theme.nick_color(nick, self.own_nick()))
+    }
+    /// Send the input, right away rather than through the event queue so keys typed after Enter
+    /// don't end up in the sent input
+    fn enter(&mut self) {
+        let input = self.input.take();
+        self.history.push(&input);
+        if let Err(e) = self.handle_input(input) {
+            self.handle_error(e);
+        }
+    }
+    /// Notify about a message for `target` unless it is shown in a focused terminal or muted
+    fn notify(&mut self, target: &Target, title: &str, body: &str) {
+        let shown = self.focused && self.buffers.current().target == *target;
+        if shown || self.muted.contains(target_name(target)) {
+            return;
+        }
+        for notifier in self.notifiers.iter_mut() {
+            notifier.notify(title, body);
+        }
+    }
+    /// Close the current buffer, parting the channel if it is a channel buffer
+    fn close_buffer(&mut self) -> ChatResult<()> {
+        match self.buffers.current().target.clone() {
+            Target::Server => {
+                self.handle_error("The server buffer can't be closed");
+                Ok(())
+            }
+            Target::Channel(_) => self.handle_command("/part".to_owned(), Vec::new()),
+            target @ Target::Nick(_) => {
+                self.buffers.close(&target);
+                Ok(())
+            }
+        }
     }
     fn handle_events(&mut self) -> anyhow::Result<()> {
         match self.events.next()? {
             Event::Connect(address) => self.connect(address),
-            Event::Disconnect => Ok(self.disconnect()),
-            Event::Connected(address) => Ok(self.connected(address)),
-            Event::Disconnected => Ok(self.disconnected()),
+            Event::Reconnect(address, generation) if generation == self.reconnect_generation => {
+                self.connect(address)
+            }
+            Event::Reconnect(..) => {
+                debug!("Scheduled reconnect cancelled");
+                Ok(())
+            }
+            Event::Disconnect => {
+                self.disconnect();
+                Ok(())
+            }
+            Event::Connected(address) => self.connected(address),
+            Event::Disconnected => {
+                // The connection may have been closed by the server rather than by us
+                if let Some(ref handler) = self.handler {
+                    if !handler.is_running() {
+                        self.handler = None;
+                    }
+                }
+                self.disconnected();
+                Ok(())
+            }
             Event::Input(input) => {
                 match input {
-                    Key::Char('\n') => self.events.tx.send(Event::Enter)?,
-                    Key::Char(c) => self.input.push(c),
-                    Key::Backspace => {
-                        self.input.pop();
-                    }
-                    Key::Up => self.history_prev(),
-                    Key::Down => self.history_next(),
-                    _ => {}
+                    InputEvent::Key(key) => self.handle_key(key)?,
+                    InputEvent::Mouse(mouse) => self.handle_mouse(mouse),
+                    InputEvent::Unsupported(sequence) => self.handle_sequence(&sequence),
                 }
                 Ok(())
             }
             Event::RecvMessage(data) => self.recv_binary(&data),
-            Event::Enter => {
-                let input = self.input.clone();
-                self.history.push(input.clone());
-                self.history_index = None;
-                self.input.clear();
-                self.handle_input(input)
+            Event::Tick => self.tick(),
+            Event::SnippetUploaded(target, Ok(url)) => {
+                self.send_message_to(target, MessageKind::Normal, url)
             }
-            Event::Nope | Event::Tick => Ok(()),
+            Event::SnippetUploaded(_, Err(e)) => {
+                self.handle_error(format!("Could not upload snippet: {}", e));
+                Ok(())
+            }
+            Event::Nope => Ok(()),
         }
         .unwrap_or_else(|e| self.handle_error(e));
         Ok(())
     }
 
-    fn history_prev(&mut self) {
-        if !self.history.is_empty() && self.history_index != Some(0) {
-            self.history_index = self
-                .history_index
-                .map(|x| (x - 1))
-                .or(Some(self.history.len() - 1));
+    fn handle_key(&mut self, key: Key) -> anyhow::Result<()> {
+        self.last_input = Instant::now();
+        if self.auto_away {
+            // Handle the key even if coming back fails, e.g. when disconnected
+            if let Err(e) = self.handle_command("/back".to_owned(), Vec::new()) {
+                self.handle_error(e);
+            }
+        }
+        if let Some(ref mut paste) = self.paste {
+            // Pasted newlines and tabs are text, not Enter and Tab. The UI can't show tabs, so they
+            // are pasted as spaces.
+            match key {
+                Key::Char('\t') => (0..TAB_WIDTH).for_each(|_| paste.push(' ')),
+                Key::Char(c) => paste.push(c),
+                _ => {}
+            }
+            return Ok(());
+        }
+        if self.pending_paste.is_some() {
+            self.handle_paste_key(key)?;
+            return Ok(());
+        }
+        if self.handle_search_key(key)? {
+            return Ok(());
+        }
+        if let Some(action) = self.key_bindings.get(&key).copied() {
+            self.perform(action);
+            return Ok(());
         }
-        if let Some(index) = self.history_index {
-            self.input = self.history[index].clone();
+        match key {
+            Key::Char('\n') => self.enter(),
+            // Alt-Enter, the terminal sends Enter as a carriage return
+            Key::Alt('\r') | Key::Alt('\n') => self.input.insert('\n'),
+            Key::Char(c) => self.input.insert(c),
+            Key::Backspace => self.input.backspace(),
+            Key::Delete => self.input.delete(),
+            Key::Left => self.input.move_left(),
+            Key::Right => self.input.move_right(),
+            Key::Home | Key::Ctrl('a') => self.input.move_home(),
+            Key::End | Key::Ctrl('e') => self.input.move_end(),
+            Key::Alt('b') => self.input.word_left(),
+            Key::Alt('f') => self.input.word_right(),
+            Key::Ctrl('w') => self.input.kill_word(),
+            Key::Ctrl('u') => self.input.kill_to_start(),
+            Key::Ctrl('k') => self.input.kill_to_end(),
+            Key::Alt(c @ '1'..='9') => self.buffers.select(c as usize - '1' as usize),
+            Key::Alt('0') => self.buffers.select(9),
+            _ => {}
         }
+        Ok(())
     }
 
-    fn history_next(&mut self) {
-        self.history_index =
-            if self.history.is_empty() || self.history_index == Some(self.history.len() - 1) {
-                None
-            } else {
-                self.history_index.map(|x| (x + 1)).or(None)
-            };
+    fn perform(&mut self, action: Action) {
+        match action {
+            Action::NextBuffer => self.buffers.select_next(),
+            Action::PrevBuffer => self.buffers.select_prev(),
+            Action::PageUp => self.scroll_up(Some(self.message_page)),
+            Action::PageDown => self
+                .buffers
+                .current_mut()
+                .messages
+                .scroll_down(self.message_page),
+            Action::ScrollTop => self.scroll_up(None),
+            Action::ScrollBottom => self.buffers.current_mut().messages.scroll_to_bottom(),
+            // Up and Down move between the lines of a multiline input before browsing history
+            Action::HistoryPrev if self.input.move_up() => {}
+            Action::HistoryNext if self.input.move_down() => {}
+            Action::HistoryPrev => {
+                if let Some(entry) = self.history.prev() {
+                    self.input.set(entry);
+                }
+            }
+            Action::HistoryNext => {
+                if let Some(entry) = self.history.next() {
+                    self.input.set(entry);
+                }
+            }
+            Action::HistorySearch => {
+                self.search = Some(HistorySearch {
+                    query: String::new(),
+                    found: None,
+                    original: self.input.text().to_owned(),
+                })
+            }
+            Action::Complete => self.complete(),
+        }
+    }
 
-        if let Some(index) = self.history_index {
-            self.input = self.history[index].clone();
+    /// Scroll the current buffer up by `messages`, or to the top when `None`
+    fn scroll_up(&mut self, messages: Option<usize>) {
+        let view = MessageView {
+            theme: &self.theme,
+            timestamp_format: self.config.timestamp_format.as_deref(),
+            own_nick: self.nick.as_deref().unwrap_or(DEFAULT_NICK),
+            width: self.message_width,
+        };
+        let rows = |line: &Line| view.rows(line).len();
+        let scrollback = &mut self.buffers.current_mut().messages;
+        match messages {
+            Some(messages) => scrollback.scroll_up(messages, self.message_height, rows),
+            None => scrollback.scroll_to_top(self.message_height, rows),
+        }
+    }
+
+    fn complete(&mut self) {
+        if let Some((input, cursor)) = self
+            .completer
+            .complete(self.input.text(), self.input.cursor())
+        {
+            self.input.set_with_cursor(input, cursor);
         }
     }
 
+    fn handle_sequence(&mut self, sequence: &[u8]) {
+        match sequence {
+            CTRL_LEFT => self.input.word_left(),
+            CTRL_RIGHT => self.input.word_right(),
+            CTRL_HOME => self.perform(Action::ScrollTop),
+            CTRL_END => self.perform(Action::ScrollBottom),
+            PASTE_START => self.paste = Some(String::new()),
+            PASTE_END => self.end_paste(),
+            FOCUS_IN => self.focused = true,
+            FOCUS_OUT => self.focused = false,
+            _ => debug!(sequence:? = sequence; "Unsupported input sequence"),
+        }
+    }
+
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        match mouse {
+            MouseEvent::Press(MouseButton::WheelUp, _, _) => {
+                self.scroll_up(Some(WHEEL_SCROLL_MESSAGES))
+            }
+            MouseEvent::Press(MouseButton::WheelDown, _, _) => self
+                .buffers
+                .current_mut()
+                .messages
+                .scroll_down(WHEEL_SCROLL_MESSAGES),
+            _ => {}
+        }
+    }
+
+    /// Insert a single line paste, a multiline one waits for the user to choose how to send it
+    fn end_paste(&mut self) {
+        let paste = match self.paste.take() {
+            Some(paste) => paste,
+            None => return,
+        };
+        // Copied lines often end with a newline that isn't meant to be sent
+        let paste = paste.trim_end_matches('\n');
+        if paste.contains('\n') {
+            self.pending_paste = Some(paste.to_owned());
+        } else {
+            self.input.insert_str(paste);
+        }
+    }
+
+    /// Handle a key choosing how to send a multiline paste, other keys are ignored
+    fn handle_paste_key(&mut self, key: Key) -> ChatResult<()> {
+        let paste = match self.pending_paste.take() {
+            Some(paste) => paste,
+            None => return Ok(()),
+        };
+        match key {
+            Key::Char('m') => self.send_message(paste),
+            Key::Char('l') => {
+                let target = self.current_target();
+                let lines = paste.lines().filter(|line| !line.trim().is_empty());
+                self.paste_lines
+                    .extend(lines.map(|line| (target.clone(), line.to_owned())));
+                Ok(())
+            }
+            Key::Char('s') if !self.config.snippet_command.is_empty() => {
+                self.upload_snippet(paste);
+                Ok(())
+            }
+            Key::Char('e') => {
+                self.input.insert_str(&paste);
+                Ok(())
+            }
+            Key::Esc | Key::Ctrl('g') => Ok(()),
+            _ => {
+                self.pending_paste = Some(paste);
+                Ok(())
+            }
+        }
+    }
+
+    /// Upload `text` with the snippet command in the background and send its URL to the current
+    /// buffer once done
+    fn upload_snippet(&mut self, text: String) {
+        let command = self.config.snippet_command.clone();
+        let target = self.current_target();
+        let tx = self.events.tx.clone();
+        self.handle_status("Uploading snippet");
+        thread::spawn(move || {
+            let result = snippet::upload(&command, &text).map_err(|e| e.to_string());
+            tx.send(Event::SnippetUploaded(target, result)).ok();
+        });
+    }
+
+    /// Handle a key during a reverse history search, returns false if the key ended the search
+    /// and should be handled as usual
+    fn handle_search_key(&mut self, key: Key) -> anyhow::Result<bool> {
+        let search = match self.search {
+            Some(ref mut search) => search,
+            None => return Ok(false),
+        };
+        let next_match = self.key_bindings.get(&key) == Some(&Action::HistorySearch);
+        match key {
+            _ if next_match => {
+                let before = search.found.unwrap_or(self.history.len());
+                if let Some(found) = self.history.search(&search.query, before) {
+                    search.found = Some(found);
+                }
+            }
+            Key::Char('\n') => {
+                self.search = None;
+                self.enter();
+                return Ok(true);
+            }
+            // Nothing to complete in the query
+            Key::Char('\t') => return Ok(true),
+            Key::Char(c) => {
+                search.query.push(c);
+                // Keep the current match if it still matches
+                let before = search.found.map_or(self.history.len(), |found| found + 1);
+                search.found = self.history.search(&search.query, before);
+            }
+            Key::Backspace => {
+                search.query.pop();
+                search.found = self.history.search(&search.query, self.history.len());
+            }
+            Key::Esc | Key::Ctrl('g') => {
+                self.input.set(search.original.clone());
+                self.search = None;
+                return Ok(true);
+            }
+            _ => {
+                // Accept the match and let the key edit it
+                self.search = None;
+                return Ok(false);
+            }
+        }
+        if let Some(found) = search.found {
+            let entry = self.history.get(found);
+            let cursor = entry.find(&search.query).unwrap_or(0);
+            self.input.set_with_cursor(entry, cursor);
+        }
+        Ok(true)
+    }
+
     fn render_ui<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> anyhow::Result<()> {
+        let input_rows = self.input.line_count().min(MAX_INPUT_ROWS);
         terminal.draw(|f| {
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
                 .margin(2)
                 .constraints(
                     [
+                        Constraint::Length(if self.config.show_help { 1 } else { 0 }),
+                        Constraint::Length(input_rows as u16 + 2),
                         Constraint::Length(1),
-                        Constraint::Length(3),
                         Constraint::Min(1),
+                        Constraint::Length(1),
                     ]
                     .as_ref(),
                 )
@@ -189,65 +938,171 @@ impl App {
             let bold_style = Style::default().add_modifier(Modifier::BOLD);
             let help_message = vec![
                 Span::raw("Type "),
-                Span::styled("/connect 127.0.0.1:3042", bold_style),
+                Span::styled(format!("/connect {}", self.config.server), bold_style),
                 Span::raw(" to connect to a server, "),
                 Span::styled("/nick MyNick", bold_style),
                 Span::raw(" to change your name, "),
+                Span::styled("/help", bold_style),
+                Span::raw(" for all commands, "),
                 Span::styled("/quit", bold_style),
                 Span::raw(" to quit."),
             ];
             let help_message_widget = Paragraph::new(Text::from(Spans::from(help_message)));
             f.render_widget(help_message_widget, chunks[0]);
 
-            let nick = self
-                .nick
-                .as_ref()
-                .map(|n| n.as_str())
-                .unwrap_or("anonymous");
+            let title = match self.search {
+                _ if self.pending_paste.is_some() => self.paste_prompt(),
+                Some(HistorySearch {
+                    ref query,
+                    found: None,
+                    ..
+                }) if !query.is_empty() => format!("failing reverse-i-search: {}", query),
+                Some(HistorySearch { ref query, .. }) => format!("reverse-i-search: {}", query),
+                None => self.own_nick().to_owned(),
+            };
 
-            let input_paragraph = Paragraph::new(self.input.as_ref())
-                .block(Block::default().borders(Borders::ALL).title(nick));
+            // Leave out the borders
+            let input_width = chunks[1].width.saturating_sub(2) as usize;
+            let (input, column, row) = self.input.view(input_width, input_rows);
+            let input: Vec<Spans> = input.into_iter().map(Spans::from).collect();
+            let input_paragraph =
+                Paragraph::new(input).block(Block::default().borders(Borders::ALL).title(title));
             f.render_widget(input_paragraph, chunks[1]);
             f.set_cursor(
-                // Put cursor past the end of the input text
-                chunks[1].x + self.input.width() as u16 + 1,
-                // Move one line down, from the border to the input line
-                chunks[1].y + 1,
+                // Move one column right, from the border to the input text
+                chunks[1].x + column as u16 + 1,
+                // Move one line down, from the border to the first input line
+                chunks[1].y + row as u16 + 1,
             );
 
-            let nick_style = Style::default().fg(Color::Magenta);
-            let status_style = Style::default().fg(Color::Gray);
-            let error_style = Style::default().fg(Color::Red);
-
-            let messages: Vec<ListItem> = self
-                .messages
+            let unread_style = Style::default().add_modifier(Modifier::BOLD);
+            let mention_style = Style::default()
+                .fg(self.theme.mention)
+                .add_modifier(Modifier::BOLD);
+            let tabs: Vec<Spans> = self
+                .buffers
                 .iter()
-                .map(|m| {
-                    let content: Text = match m {
-                        Message::Chat { nick, content } => Spans::from(vec![
-                            Span::styled(nick, nick_style),
-                            Span::from(format!(": {}", content)),
+                .enumerate()
+                .map(|(index, buffer)| {
+                    let name = Span::raw(format!("{}:{}", index + 1, buffer.name()));
+                    if buffer.mentions > 0 {
+                        Spans::from(vec![
+                            name,
+                            Span::styled(format!(" ({}!)", buffer.unread), mention_style),
                         ])
-                        .into(),
-                        Message::ChangeNick { nick } => Spans::from(vec![
-                            Span::from("Changed nick to "),
-                            Span::styled(nick, nick_style),
+                    } else if buffer.unread > 0 {
+                        Spans::from(vec![
+                            name,
+                            Span::styled(format!(" ({})", buffer.unread), unread_style),
                         ])
-                        .into(),
-                        Message::Status { content } => Span::styled(content, status_style).into(),
-                        Message::Error { content } => Span::styled(content, error_style).into(),
-                    };
-                    ListItem::new(content)
+                    } else {
+                        Spans::from(name)
+                    }
                 })
                 .collect();
+            let tabs = Tabs::new(tabs)
+                .select(self.buffers.current_index())
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            f.render_widget(tabs, chunks[2]);
+
+            let buffer = self.buffers.current();
+            let message_area = if let Target::Channel(_) = buffer.target {
+                let areas = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Min(1), Constraint::Length(NICK_LIST_WIDTH)].as_ref())
+                    .split(chunks[3]);
+                let members: Vec<ListItem> = buffer
+                    .members
+                    .iter()
+                    .map(|member| {
+                        // Away users are grayed out
+                        let nick_style = if member.away {
+                            Style::default().fg(self.theme.status)
+                        } else {
+                            self.nick_style(&member.nick)
+                        };
+                        if member.operator {
+                            ListItem::new(Spans::from(vec![
+                                Span::styled("@", bold_style),
+                                Span::styled(&member.nick, nick_style),
+                            ]))
+                        } else {
+                            ListItem::new(Span::styled(&member.nick, nick_style))
+                        }
+                    })
+                    .collect();
+                let title = format!("Users ({})", buffer.members.len());
+                let members =
+                    List::new(members).block(Block::default().borders(Borders::ALL).title(title));
+                f.render_widget(members, areas[1]);
+                areas[0]
+            } else {
+                chunks[3]
+            };
+
+            // Leave out the borders
+            self.message_height = message_area.height.saturating_sub(2) as usize;
+            self.message_width = message_area.width.saturating_sub(2) as usize;
+            let view = MessageView {
+                theme: &self.theme,
+                timestamp_format: self.config.timestamp_format.as_deref(),
+                own_nick: self.own_nick(),
+                width: self.message_width,
+            };
+            let visible = buffer
+                .messages
+                .visible(self.message_height, |line| view.rows(line).len());
+            let mut rows: Vec<Spans> = visible.iter().flat_map(|line| view.rows(line)).collect();
+            // Rows of the oldest message are cut from the top when the visible messages don't fit
+            let cut = rows.len().saturating_sub(self.message_height);
+            self.message_page = (visible.len() - (cut > 0) as usize).max(1);
+            let messages: Vec<ListItem> = rows.drain(cut..).map(ListItem::new).collect();
+            let title = match buffer.messages.unseen() {
+                0 => buffer.name().to_owned(),
+                1 => format!("{} (1 new message)", buffer.name()),
+                unseen => format!("{} ({} new messages)", buffer.name(), unseen),
+            };
             let messages =
-                List::new(messages).block(Block::default().borders(Borders::ALL).title("Messages"));
-            f.render_widget(messages, chunks[2]);
+                List::new(messages).block(Block::default().borders(Borders::ALL).title(title));
+            f.render_widget(messages, message_area);
+
+            let connection = match (self.is_connected(), self.server.as_deref()) {
+                (true, Some(server)) => format!("Connected to {} via {}", server, TRANSPORT),
+                _ => "Disconnected".to_owned(),
+            };
+            let latency = match self.latency {
+                Some(latency) if self.is_connected() => {
+                    format!(" | latency {} ms", latency.as_millis())
+                }
+                _ => String::new(),
+            };
+            let topic = match buffer.topic {
+                Some(ref topic) => format!(" | topic {}", topic.text),
+                None => String::new(),
+            };
+            let away = if self.away.is_some() { " (away)" } else { "" };
+            let status = format!(
+                "{}{} | nick {}{}{}",
+                connection,
+                latency,
+                self.nick.as_deref().unwrap_or(DEFAULT_NICK),
+                away,
+                topic
+            );
+            let status =
+                Paragraph::new(status).style(Style::default().add_modifier(Modifier::REVERSED));
+            f.render_widget(status, chunks[4]);
         })?;
         Ok(())
     }
 }
 
+impl Default for Events {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Events {
     pub fn new() -> Events {
         Events::with_tick_rate(Duration::from_millis(250))
@@ -259,12 +1114,10 @@ impl Events {
             let tx = tx.clone();
             thread::spawn(move || {
                 let stdin = io::stdin();
-                for evt in stdin.keys() {
-                    if let Ok(key) = evt {
-                        if let Err(err) = tx.send(Event::Input(key)) {
-                            eprintln!("{}", err);
-                            return;
-                        }
+                for event in stdin.events().flatten() {
+                    if let Err(err) = tx.send(Event::Input(event)) {
+                        error!(error:% = err; "Could not send input event");
+                        return;
                     }
                 }
             })
@@ -286,18 +1139,252 @@ impl Events {
         }
     }
 
-    pub fn next(&self) -> Result<Event<Key>, mpsc::RecvError> {
+    pub fn next(&self) -> Result<Event<InputEvent>, mpsc::RecvError> {
         self.rx.recv()
     }
 }
 
 impl ChatUserInterface for App {
-    fn receive_message(&mut self, nick: String, content: String) {
-        self.messages.push(Message::Chat { nick, content });
+    fn receive_message(&mut self, nick: String, kind: MessageKind, content: String) {
+        if nick != self.own_nick() {
+            self.completer.add_nick(&nick);
+        }
+        let highlight = self.is_highlight(&nick, &content);
+        if highlight {
+            self.notify(&Target::Server, &nick, &content);
+        }
+        let message = Message::Chat {
+            nick,
+            kind,
+            content,
+            highlight,
+        };
+        self.buffers.push(Target::Server, message, highlight);
+    }
+    fn receive_notice(&mut self, content: String) {
+        self.buffers.push_current(Message::Notice { content });
+    }
+    fn receive_channel_message(
+        &mut self,
+        channel: String,
+        nick: String,
+        kind: MessageKind,
+        content: String,
+    ) {
+        if nick != self.own_nick() {
+            self.completer.add_nick(&nick);
+        }
+        let highlight = self.is_highlight(&nick, &content);
+        let target = Target::Channel(channel);
+        if highlight {
+            let title = format!("{} in {}", nick, target_name(&target));
+            self.notify(&target, &title, &content);
+        }
+        let message = Message::Chat {
+            nick,
+            kind,
+            content,
+            highlight,
+        };
+        self.buffers.push(target, message, highlight);
+    }
+    fn receive_private_message(
+        &mut self,
+        from: String,
+        to: String,
+        kind: MessageKind,
+        content: String,
+    ) {
+        // Messages we sent are echoed back by the server
+        let sent = from == self.own_nick();
+        let partner = if sent { to } else { from.clone() };
+        self.completer.add_nick(&partner);
+        let highlight = self.is_highlight(&from, &content);
+        let target = Target::Nick(partner);
+        if !sent {
+            self.notify(&target, &from, &content);
+        }
+        let message = Message::Chat {
+            nick: from,
+            kind,
+            content,
+            highlight,
+        };
+        self.buffers.push(target, message, !sent);
+    }
+    fn user_joined(&mut self, channel: String, nick: String) {
+        let content = format!("{} joined {}", nick, channel);
+        self.buffers
+            .push_if_open(&Target::Channel(channel), Message::Status { content });
+    }
+    fn user_parted(&mut self, channel: String, nick: String) {
+        let content = format!("{} left {}", nick, channel);
+        self.buffers
+            .push_if_open(&Target::Channel(channel), Message::Status { content });
+    }
+    fn channel_members(&mut self, channel: String, members: Vec<Member>) {
+        for member in members.iter() {
+            if member.nick != self.own_nick() {
+                self.completer.add_nick(&member.nick);
+            }
+        }
+        if let Some(buffer) = self.buffers.get_mut(&Target::Channel(channel)) {
+            buffer.members = members;
+        }
+    }
+    fn channel_topic(&mut self, channel: String, topic: Option<Topic>) {
+        let target = Target::Channel(channel.clone());
+        let content = describe_topic(&channel, topic.as_ref());
+        self.buffers
+            .push_if_open(&target, Message::Status { content });
+        if let Some(buffer) = self.buffers.get_mut(&target) {
+            buffer.topic = topic;
+        }
+    }
+    fn show_topic(&mut self, channel: String) {
+        let topic = self
+            .buffers
+            .get_mut(&Target::Channel(channel.clone()))
+            .and_then(|buffer| buffer.topic.as_ref());
+        let content = describe_topic(&channel, topic);
+        self.handle_status(content);
+    }
+    fn user_list(&mut self, channel: Option<String>, users: Vec<UserInfo>) {
+        let title = match channel {
+            Some(channel) => format!("Users on {} ({})", channel, users.len()),
+            None => format!("Users on the server ({})", users.len()),
+        };
+        let rows: Vec<Vec<String>> = users
+            .into_iter()
+            .map(|user| {
+                let operator = if user.operator { "@" } else { "" };
+                vec![
+                    format!("{}{}", operator, user.nick),
+                    format_idle(user.idle),
+                    user.away.unwrap_or_default(),
+                ]
+            })
+            .collect();
+        let table = format_table(&["NICK", "IDLE", "AWAY"], &rows);
+        self.handle_status(format!("{}\n{}", title, table));
+    }
+    fn channel_list(&mut self, channels: Vec<ChannelInfo>) {
+        if channels.is_empty() {
+            self.handle_status("No channels, create one with /join #channel");
+            return;
+        }
+        let rows: Vec<Vec<String>> = channels
+            .into_iter()
+            .map(|channel| {
+                let topic = channel.topic.map(|topic| topic.text).unwrap_or_default();
+                vec![channel.name, channel.members.to_string(), topic]
+            })
+            .collect();
+        let table = format_table(&["CHANNEL", "USERS", "TOPIC"], &rows);
+        self.handle_status(format!("Channels ({})\n{}", rows.len(), table));
+    }
+    fn receive_pong(&mut self, token: u64) {
+        if let Some((sent_token, sent)) = self.ping {
+            if sent_token == token {
+                self.latency = Some(sent.elapsed());
+            }
+        }
     }
     fn change_nick(&mut self, nick: String) {
         self.nick = Some(nick.clone());
-        self.messages.push(Message::ChangeNick { nick });
+        self.buffers.push_current(Message::ChangeNick { nick });
+    }
+    fn change_away(&mut self, reason: Option<String>) {
+        match reason {
+            Some(ref reason) => self.handle_status(format!("You are away: {}", reason)),
+            None => self.handle_status("You are back"),
+        }
+        self.away = reason;
+        self.auto_away = false;
+    }
+    fn join_channel(&mut self, channel: String) {
+        self.completer.add_channel(&channel);
+        self.buffers.open(Target::Channel(channel));
+    }
+    fn part_channel(&mut self, channel: String) {
+        self.completer.remove_channel(&channel);
+        self.buffers.close(&Target::Channel(channel));
+    }
+    fn current_target(&self) -> Target {
+        self.buffers.current().target.clone()
+    }
+    fn commands(&self) -> &CommandRegistry {
+        &self.commands
+    }
+    fn commands_mut(&mut self) -> &mut CommandRegistry {
+        &mut self.commands
+    }
+    fn aliases_changed(&mut self) {
+        self.completer.set_commands(self.commands.names());
+        if let Err(e) = self.alias_file.save(self.commands.aliases()) {
+            self.handle_error(format!("Could not save aliases: {}", e));
+        }
+    }
+    fn handle_ui_command(
+        &mut self,
+        spec: &'static CommandSpec,
+        params: Vec<String>,
+    ) -> ChatResult<()> {
+        match (spec.name, params.as_slice()) {
+            ("/close", []) => self.close_buffer(),
+            ("/mute", name) => {
+                let name = name
+                    .first()
+                    .map_or_else(|| self.buffers.current().name().to_owned(), String::clone);
+                self.handle_status(format!("Muted notifications from {}", name));
+                self.muted.insert(name);
+                Ok(())
+            }
+            ("/log", [state]) if state != "on" && state != "off" => {
+                Err(spec.invalid(format!("expected on or off, got {}", state)))
+            }
+            ("/log", state) => {
+                let transcript = self.buffers.transcript_mut();
+                if let Some(state) = state.first() {
+                    transcript.enabled = state == "on";
+                }
+                let status = match (transcript.enabled, transcript.directory()) {
+                    (true, Some(directory)) => format!("Logging to {}", directory.display()),
+                    (true, None) => "Logging enabled but there is no log directory".to_owned(),
+                    (false, _) => "Logging disabled".to_owned(),
+                };
+                self.handle_status(status);
+                Ok(())
+            }
+            ("/unmute", name) => {
+                let name = name
+                    .first()
+                    .map_or_else(|| self.buffers.current().name().to_owned(), String::clone);
+                self.handle_status(format!("Unmuted notifications from {}", name));
+                self.muted.remove(&name);
+                Ok(())
+            }
+            _ => Err(ChatError::Unexpected),
+        }
+    }
+    fn show_info(&mut self, content: String) {
+        self.handle_status(content);
+    }
+    fn server_shutdown(&mut self, reason: String, reconnect_after: Option<Duration>) {
+        self.handle_status(format!("Server shutting down: {}", reason));
+        self.disconnect();
+        if !self.config.auto_reconnect {
+            return;
+        }
+        if let (Some(delay), Some(address)) = (reconnect_after, self.server.clone()) {
+            self.handle_status(format!("Reconnecting in {} seconds", delay.as_secs()));
+            let generation = self.reconnect_generation;
+            let sender = self.events.tx.clone();
+            thread::spawn(move || {
+                thread::sleep(delay);
+                sender.send(Event::Reconnect(address, generation)).ok();
+            });
+        }
     }
     fn quit(&mut self) {
         self.disconnect();
@@ -308,8 +1395,9 @@ impl ChatUserInterface for App {
 impl ChatClient for App {
     fn connect(&mut self, address: String) -> ChatResult<()> {
         if self.is_connected() {
-            return Err(ChatError::AlreadyConnected.into());
+            return Err(ChatError::AlreadyConnected);
         }
+        self.reconnect_generation += 1;
 
         let remote_addr = address
             .to_remote_addr()
@@ -317,14 +1405,15 @@ impl ChatClient for App {
                 address: address.clone(),
             })?;
         if !remote_addr.is_socket_addr() {
-            return Err(ChatError::InvalidAddress { address }.into());
+            return Err(ChatError::InvalidAddress { address });
         }
 
+        info!(address = address.as_str(); "Connecting");
         let (handler, listener) = node::split();
 
         let (server, _) = handler
             .network()
-            .connect(Transport::FramedTcp, remote_addr)
+            .connect(TRANSPORT, remote_addr)
             .map_err(|_| ChatError::ConnectionError)?;
         self.events
             .tx
@@ -344,18 +1433,24 @@ impl ChatClient for App {
                 },
                 NodeEvent::Network(net_event) => match net_event {
                     NetEvent::Message(_endpoint, data) => {
+                        debug!(bytes = data.len(); "Message received");
                         listener_sender.send(Event::RecvMessage(data.to_vec())).ok();
                     }
-                    _ => unreachable!(),
+                    NetEvent::Disconnected(_endpoint) => listener_handler.stop(),
+                    NetEvent::Connected(..) => unreachable!(),
                 },
             });
+            info!("Disconnected");
             sender.send(Event::Disconnected).ok();
         });
 
+        self.buffers.transcript_mut().set_server(&address);
+        self.server = Some(address);
         self.handler = Some(handler);
         Ok(())
     }
     fn disconnect(&mut self) {
+        self.reconnect_generation += 1;
         if let Some(handler) = self.handler.take() {
             handler.stop();
         }
@@ -368,7 +1463,54 @@ impl ChatClient for App {
             handler.signals().send(ChatSignal::Message { data });
             Ok(())
         } else {
-            Err(ChatError::SendError.into())
+            Err(ChatError::SendError)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn row(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|cell| cell.to_string()).collect()
+    }
+
+    #[test]
+    fn table_columns_align_by_display_width() {
+        let rows = [
+            row(&["ferris", "1m", ""]),
+            row(&["日本語", "2h", "lunch"]),
+            row(&["a\u{301}", "", ""]),
+        ];
+        let table = format_table(&["NICK", "IDLE", "AWAY"], &rows);
+        assert_eq!(
+            table,
+            "NICK    IDLE  AWAY\n\
+             ferris  1m\n\
+             日本語  2h    lunch\n\
+             a\u{301}"
+        );
+    }
+
+    #[test]
+    fn table_without_rows_is_the_header() {
+        assert_eq!(format_table(&["CHANNEL", "USERS"], &[]), "CHANNEL  USERS");
+    }
+
+    #[test]
+    fn describes_topics_and_missing_ones() {
+        let set_at = Local.ymd(2024, 1, 2).and_hms(3, 4, 5);
+        let topic = Topic {
+            text: "Rust things".to_owned(),
+            set_by: "ferris".to_owned(),
+            set_at: set_at.into(),
+        };
+        assert_eq!(
+            describe_topic("#rust", Some(&topic)),
+            "Topic of #rust: Rust things (set by ferris on 2024-01-02 03:04)"
+        );
+        assert_eq!(describe_topic("#rust", None), "No topic on #rust");
+    }
+}
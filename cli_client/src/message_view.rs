@@ -0,0 +1,185 @@
+use chatrs::MessageKind;
+use tui::style::{Modifier, Style};
+use tui::text::{Span, Spans};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::buffer::Line;
+use crate::theme::Theme;
+use crate::Message;
+
+/// Lays out messages in rows of the message view
+pub struct MessageView<'a> {
+    pub theme: &'a Theme,
+    pub timestamp_format: Option<&'a str>,
+    pub own_nick: &'a str,
+    /// Columns available for a row
+    pub width: usize,
+}
+
+impl<'a> MessageView<'a> {
+    /// Rows of a message, wrapped to the width with continuation rows indented under the text
+    /// after the nick
+    pub fn rows<'b>(&self, line: &'b Line) -> Vec<Spans<'b>> {
+        let mut prefix = Vec::new();
+        if let Some(format) = self.timestamp_format {
+            let time = format!("{} ", line.time.format(format));
+            prefix.push(Span::styled(
+                time,
+                Style::default().fg(self.theme.timestamp),
+            ));
+        }
+        let (content, style) = match line.message {
+            Message::Chat {
+                ref nick,
+                kind,
+                ref content,
+                highlight,
+            } => {
+                let nick_span = Span::styled(nick.as_str(), self.nick_style(nick));
+                let style = match kind {
+                    MessageKind::Normal | MessageKind::Action => Style::default(),
+                    MessageKind::Notice => Style::default().fg(self.theme.notice),
+                    MessageKind::System => Style::default().fg(self.theme.status),
+                };
+                match kind {
+                    MessageKind::Normal | MessageKind::System => {
+                        prefix.extend(vec![nick_span, Span::raw(": ")])
+                    }
+                    MessageKind::Action => {
+                        prefix.extend(vec![Span::raw("* "), nick_span, Span::raw(" ")])
+                    }
+                    MessageKind::Notice => {
+                        prefix.extend(vec![Span::raw("-"), nick_span, Span::raw("- ")])
+                    }
+                }
+                let style = if highlight {
+                    style.fg(self.theme.mention).add_modifier(Modifier::BOLD)
+                } else {
+                    style
+                };
+                (content.as_str(), style)
+            }
+            Message::ChangeNick { ref nick } => {
+                prefix.push(Span::raw("Changed nick to "));
+                (nick.as_str(), self.nick_style(nick))
+            }
+            Message::Notice { ref content } => {
+                (content.as_str(), Style::default().fg(self.theme.notice))
+            }
+            Message::Status { ref content } => {
+                (content.as_str(), Style::default().fg(self.theme.status))
+            }
+            Message::Error { ref content } => {
+                (content.as_str(), Style::default().fg(self.theme.error))
+            }
+        };
+
+        // Fall back to no indent when the prefix would leave little room for the text
+        let prefix_width: usize = prefix.iter().map(Span::width).sum();
+        let indent = if prefix_width * 2 <= self.width {
+            prefix_width
+        } else {
+            0
+        };
+        let mut rows = Vec::new();
+        for (index, row) in wrap(content, self.width - indent).into_iter().enumerate() {
+            let mut spans = if index == 0 {
+                std::mem::take(&mut prefix)
+            } else {
+                vec![Span::raw(" ".repeat(indent))]
+            };
+            spans.push(Span::styled(row, style));
+            rows.push(Spans::from(spans));
+        }
+        rows
+    }
+
+    pub fn nick_style(&self, nick: &str) -> Style {
+        Style::default().fg(self.theme.nick_color(nick, self.own_nick))
+    }
+}
+
+/// Split `text` into rows of at most `width` columns, breaking at whitespace where possible and
+/// within words longer than a row. Newlines always start a new row.
+pub fn wrap(text: &str, width: usize) -> Vec<&str> {
+    let width = width.max(1);
+    let mut rows = Vec::new();
+    for line in text.split('\n') {
+        // Byte range of the current row and its width, trailing whitespace is left out of the range
+        let mut start = 0;
+        let mut end = 0;
+        let mut column = 0;
+        for (index, word) in line.split_word_bound_indices() {
+            let word_width = word.width();
+            let whitespace = word.trim().is_empty();
+            if column + word_width <= width {
+                column += word_width;
+                if !whitespace {
+                    end = index + word.len();
+                }
+                continue;
+            }
+            if whitespace {
+                rows.push(&line[start..end]);
+                start = index + word.len();
+                end = start;
+                column = 0;
+                continue;
+            }
+            if column > 0 && word_width <= width {
+                rows.push(&line[start..end]);
+                start = index;
+                column = 0;
+            }
+            for (offset, grapheme) in word.grapheme_indices(true) {
+                let grapheme_width = grapheme.width();
+                if column > 0 && column + grapheme_width > width {
+                    rows.push(&line[start..end]);
+                    start = index + offset;
+                    column = 0;
+                }
+                column += grapheme_width;
+                end = index + offset + grapheme.len();
+            }
+        }
+        rows.push(&line[start..end.max(start)]);
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_text_is_one_row() {
+        assert_eq!(wrap("hello world", 20), ["hello world"]);
+        assert_eq!(wrap("", 5), [""]);
+    }
+
+    #[test]
+    fn breaks_at_whitespace() {
+        assert_eq!(wrap("hello world", 7), ["hello", "world"]);
+        assert_eq!(wrap("hello world foo", 11), ["hello world", "foo"]);
+        assert_eq!(wrap("a b c d", 3), ["a b", "c d"]);
+    }
+
+    #[test]
+    fn breaks_long_words() {
+        assert_eq!(wrap("abcdefgh", 3), ["abc", "def", "gh"]);
+        assert_eq!(wrap("see abcdefgh", 5), ["see a", "bcdef", "gh"]);
+        assert_eq!(wrap("ab", 0), ["a", "b"]);
+    }
+
+    #[test]
+    fn newlines_start_rows() {
+        assert_eq!(wrap("a\n\nb c", 10), ["a", "", "b c"]);
+    }
+
+    #[test]
+    fn measures_display_width() {
+        assert_eq!(wrap("日本語です", 4), ["日本", "語で", "す"]);
+        assert_eq!(wrap("ne\u{301}e\u{301}", 2), ["ne\u{301}", "e\u{301}"]);
+    }
+}
@@ -0,0 +1,68 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use log::warn;
+
+use crate::config::NotificationConfig;
+
+/// Tells the user about a mention or private message they are not looking at
+pub trait Notifier {
+    fn notify(&mut self, title: &str, body: &str);
+}
+
+/// Rings the terminal bell
+pub struct Bell;
+
+impl Notifier for Bell {
+    fn notify(&mut self, _title: &str, _body: &str) {
+        let mut stdout = io::stdout();
+        if let Err(e) = stdout.write_all(b"\x07").and_then(|_| stdout.flush()) {
+            warn!(error:% = e; "Could not ring the bell");
+        }
+    }
+}
+
+/// Runs a command with the title and body appended to its arguments, e.g. `notify-send` for desktop
+/// notifications over D-Bus
+pub struct CommandNotifier {
+    program: String,
+    args: Vec<String>,
+}
+
+impl Notifier for CommandNotifier {
+    fn notify(&mut self, title: &str, body: &str) {
+        let child = Command::new(&self.program)
+            .args(&self.args)
+            .arg(title)
+            .arg(body)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        match child {
+            // Reap the process without blocking the UI
+            Ok(mut child) => {
+                thread::spawn(move || child.wait());
+            }
+            Err(e) => {
+                warn!(program = self.program.as_str(), error:% = e; "Could not run notification command")
+            }
+        }
+    }
+}
+
+/// Notifiers enabled in the configuration
+pub fn notifiers(config: &NotificationConfig) -> Vec<Box<dyn Notifier>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    if config.bell {
+        notifiers.push(Box::new(Bell));
+    }
+    if let Some((program, args)) = config.command.split_first() {
+        notifiers.push(Box::new(CommandNotifier {
+            program: program.clone(),
+            args: args.to_vec(),
+        }));
+    }
+    notifiers
+}
@@ -0,0 +1,412 @@
+use std::io::{self, BufRead};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chatrs::client::{
+    ChatClient, ChatClientCommon, ChatError, ChatResult, ChatUserInterface, CommandRegistry, Target,
+};
+use chatrs::command::CommandSpec;
+use chatrs::{ChannelInfo, ClientMessage, Member, MessageKind, Topic, UserInfo, DEFAULT_NICK};
+use log::{debug, info};
+use message_io::network::{NetEvent, ToRemoteAddr};
+use message_io::node::{self, NodeEvent, NodeHandler};
+use serde::Serialize;
+
+use crate::TRANSPORT;
+
+/// Settings of the pipe mode, sending stdin lines as messages without the terminal UI
+pub struct PipeOptions {
+    pub server: String,
+    pub nick: Option<String>,
+    /// Channel to join and send to, the lines go to the server-wide chat when unset
+    pub channel: Option<String>,
+    /// Time between sent messages at least
+    pub interval: Duration,
+    /// Print received messages to stdout and keep running after stdin ends
+    pub tail: bool,
+    /// Print received messages as JSON Lines instead of plain text
+    pub json: bool,
+}
+
+enum PipeEvent {
+    Line(String),
+    Eof,
+    RecvMessage(Vec<u8>),
+    Disconnected,
+}
+
+/// Received messages as printed by `--tail`
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Output {
+    Message {
+        nick: String,
+        // The output's own tag is also called kind
+        #[serde(rename = "message_kind")]
+        kind: MessageKind,
+        content: String,
+    },
+    ChannelMessage {
+        channel: String,
+        nick: String,
+        #[serde(rename = "message_kind")]
+        kind: MessageKind,
+        content: String,
+    },
+    PrivateMessage {
+        from: String,
+        to: String,
+        #[serde(rename = "message_kind")]
+        kind: MessageKind,
+        content: String,
+    },
+    Notice {
+        content: String,
+    },
+    Joined {
+        channel: String,
+        nick: String,
+    },
+    Parted {
+        channel: String,
+        nick: String,
+    },
+    /// Topic of a channel on joining it or when it changes, None when removed
+    Topic {
+        channel: String,
+        topic: Option<String>,
+        set_by: Option<String>,
+    },
+}
+
+struct Pipe {
+    options: PipeOptions,
+    nick: String,
+    running: bool,
+    /// Token of the ping sent after stdin ended, all lines have been handled by the server when
+    /// its pong arrives
+    closing: Option<u64>,
+    commands: CommandRegistry,
+    handler: Option<NodeHandler<Vec<u8>>>,
+    events: mpsc::Sender<PipeEvent>,
+}
+
+/// Time between messages sent at `rate` messages per second, None unless `rate` is positive and
+/// the time fits a `Duration`
+pub fn send_interval(rate: f64) -> Option<Duration> {
+    if rate > 0.0 {
+        Duration::try_from_secs_f64(1.0 / rate).ok()
+    } else {
+        None
+    }
+}
+
+/// Connect, send each line of stdin as a message and optionally print what is received until
+/// stdin ends or, with `tail`, until the server disconnects
+pub fn run(options: PipeOptions) -> anyhow::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let interval = options.interval;
+    let mut pipe = Pipe {
+        nick: DEFAULT_NICK.to_owned(),
+        options,
+        running: true,
+        closing: None,
+        commands: CommandRegistry::default(),
+        handler: None,
+        events: tx.clone(),
+    };
+    pipe.connect(pipe.options.server.clone())?;
+    if let Some(nick) = pipe.options.nick.clone() {
+        pipe.handle_command("/nick".to_owned(), vec![nick])?;
+    }
+    if let Some(channel) = pipe.options.channel.clone() {
+        pipe.handle_command("/join".to_owned(), vec![channel])?;
+    }
+
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            match line {
+                Ok(line) => tx.send(PipeEvent::Line(line)).ok(),
+                Err(_) => break,
+            };
+        }
+        tx.send(PipeEvent::Eof).ok();
+    });
+
+    let mut next_send = Instant::now();
+    while pipe.running {
+        match rx.recv()? {
+            PipeEvent::Line(line) if line.trim().is_empty() => {}
+            PipeEvent::Line(line) => {
+                let now = Instant::now();
+                if next_send > now {
+                    thread::sleep(next_send - now);
+                }
+                next_send = Instant::now() + interval;
+                pipe.send_message(line)?;
+            }
+            PipeEvent::Eof if !pipe.options.tail => {
+                debug!("Stdin ended, waiting for the server to handle the sent lines");
+                pipe.closing = Some(0);
+                pipe.send(ClientMessage::Ping { token: 0 })?;
+            }
+            PipeEvent::Eof => {}
+            PipeEvent::RecvMessage(data) => pipe.recv_binary(&data)?,
+            PipeEvent::Disconnected => {
+                pipe.handler = None;
+                anyhow::bail!("Disconnected from {}", pipe.options.server);
+            }
+        }
+    }
+    pipe.disconnect();
+    Ok(())
+}
+
+impl Pipe {
+    fn output(&self, output: Output) {
+        if !self.options.tail {
+            return;
+        }
+        if self.options.json {
+            match serde_json::to_string(&output) {
+                Ok(json) => println!("{}", json),
+                Err(e) => eprintln!("Could not format message: {}", e),
+            }
+            return;
+        }
+        match output {
+            Output::Message {
+                nick,
+                kind,
+                content,
+            } => println!("{}", said(&nick, kind, &content)),
+            Output::ChannelMessage {
+                channel,
+                nick,
+                kind,
+                content,
+            } => println!("[{}] {}", channel, said(&nick, kind, &content)),
+            Output::PrivateMessage {
+                from,
+                to,
+                kind,
+                content,
+            } => println!("[{} -> {}] {}", from, to, said(&from, kind, &content)),
+            Output::Notice { content } => println!("-!- {}", content),
+            Output::Joined { channel, nick } => println!("*** {} joined {}", nick, channel),
+            Output::Parted { channel, nick } => println!("*** {} left {}", nick, channel),
+            Output::Topic {
+                channel,
+                topic: Some(topic),
+                set_by,
+            } => println!(
+                "*** Topic of {}: {} (set by {})",
+                channel,
+                topic,
+                set_by.unwrap_or_default()
+            ),
+            Output::Topic { channel, .. } => println!("*** Topic of {} removed", channel),
+        }
+    }
+}
+
+/// A chat message as plain text, "nick: text" or "* nick waves" for actions
+fn said(nick: &str, kind: MessageKind, content: &str) -> String {
+    match kind {
+        MessageKind::Normal | MessageKind::System => format!("{}: {}", nick, content),
+        MessageKind::Action => format!("* {} {}", nick, content),
+        MessageKind::Notice => format!("-{}- {}", nick, content),
+    }
+}
+
+impl ChatUserInterface for Pipe {
+    fn receive_message(&mut self, nick: String, kind: MessageKind, content: String) {
+        // Don't echo the piped lines
+        if nick != self.nick {
+            self.output(Output::Message {
+                nick,
+                kind,
+                content,
+            });
+        }
+    }
+    fn receive_notice(&mut self, content: String) {
+        self.output(Output::Notice { content });
+    }
+    fn receive_channel_message(
+        &mut self,
+        channel: String,
+        nick: String,
+        kind: MessageKind,
+        content: String,
+    ) {
+        if nick != self.nick {
+            self.output(Output::ChannelMessage {
+                channel,
+                nick,
+                kind,
+                content,
+            });
+        }
+    }
+    fn receive_private_message(
+        &mut self,
+        from: String,
+        to: String,
+        kind: MessageKind,
+        content: String,
+    ) {
+        if from != self.nick {
+            self.output(Output::PrivateMessage {
+                from,
+                to,
+                kind,
+                content,
+            });
+        }
+    }
+    fn user_joined(&mut self, channel: String, nick: String) {
+        self.output(Output::Joined { channel, nick });
+    }
+    fn user_parted(&mut self, channel: String, nick: String) {
+        self.output(Output::Parted { channel, nick });
+    }
+    fn channel_members(&mut self, _channel: String, _members: Vec<Member>) {}
+    fn channel_topic(&mut self, channel: String, topic: Option<Topic>) {
+        let (topic, set_by) = match topic {
+            Some(topic) => (Some(topic.text), Some(topic.set_by)),
+            None => (None, None),
+        };
+        self.output(Output::Topic {
+            channel,
+            topic,
+            set_by,
+        });
+    }
+    // Pipe mode sends lines as messages, /topic, /who and /list can't be given
+    fn show_topic(&mut self, _channel: String) {}
+    fn user_list(&mut self, _channel: Option<String>, _users: Vec<UserInfo>) {}
+    fn channel_list(&mut self, _channels: Vec<ChannelInfo>) {}
+    fn receive_pong(&mut self, token: u64) {
+        if self.closing == Some(token) {
+            self.running = false;
+        }
+    }
+    fn change_nick(&mut self, nick: String) {
+        self.nick = nick;
+    }
+    // Pipe mode sends lines as messages, /away can't be given
+    fn change_away(&mut self, _reason: Option<String>) {}
+    fn join_channel(&mut self, _channel: String) {}
+    fn part_channel(&mut self, _channel: String) {}
+    fn current_target(&self) -> Target {
+        match self.options.channel {
+            Some(ref channel) => Target::Channel(channel.clone()),
+            None => Target::Server,
+        }
+    }
+    fn server_shutdown(&mut self, reason: String, _reconnect_after: Option<Duration>) {
+        eprintln!("Server shutting down: {}", reason);
+        self.running = false;
+    }
+    fn commands(&self) -> &CommandRegistry {
+        &self.commands
+    }
+    fn commands_mut(&mut self) -> &mut CommandRegistry {
+        &mut self.commands
+    }
+    // Pipe mode sends lines as messages, aliases can't be defined
+    fn aliases_changed(&mut self) {}
+    fn handle_ui_command(
+        &mut self,
+        spec: &'static CommandSpec,
+        _params: Vec<String>,
+    ) -> ChatResult<()> {
+        // Pipe mode registers no commands of its own
+        Err(ChatError::UnknownCommand {
+            name: spec.name.to_owned(),
+        })
+    }
+    fn show_info(&mut self, content: String) {
+        eprintln!("{}", content);
+    }
+    fn quit(&mut self) {
+        self.running = false;
+    }
+}
+
+impl ChatClient for Pipe {
+    fn connect(&mut self, address: String) -> ChatResult<()> {
+        let remote_addr = address
+            .to_remote_addr()
+            .map_err(|_| ChatError::InvalidAddress {
+                address: address.clone(),
+            })?;
+        if !remote_addr.is_socket_addr() {
+            return Err(ChatError::InvalidAddress { address });
+        }
+
+        info!(address = address.as_str(); "Connecting");
+        let (handler, listener) = node::split::<Vec<u8>>();
+        let (server, _) = handler
+            .network()
+            .connect(TRANSPORT, remote_addr)
+            .map_err(|_| ChatError::ConnectionError)?;
+
+        let sender = self.events.clone();
+        let listener_handler = handler.clone();
+        thread::spawn(move || {
+            let listener_sender = sender.clone();
+            listener.for_each(move |event| match event {
+                NodeEvent::Signal(data) => {
+                    listener_handler.network().send(server, &data);
+                }
+                NodeEvent::Network(NetEvent::Message(_endpoint, data)) => {
+                    listener_sender
+                        .send(PipeEvent::RecvMessage(data.to_vec()))
+                        .ok();
+                }
+                NodeEvent::Network(NetEvent::Disconnected(_endpoint)) => listener_handler.stop(),
+                NodeEvent::Network(NetEvent::Connected(..)) => unreachable!(),
+            });
+            info!("Disconnected");
+            sender.send(PipeEvent::Disconnected).ok();
+        });
+
+        self.handler = Some(handler);
+        Ok(())
+    }
+    fn disconnect(&mut self) {
+        if let Some(handler) = self.handler.take() {
+            handler.stop();
+        }
+    }
+    fn is_connected(&self) -> bool {
+        self.handler.is_some()
+    }
+    fn send_binary(&mut self, data: Vec<u8>) -> ChatResult<()> {
+        match self.handler {
+            Some(ref handler) => {
+                handler.signals().send(data);
+                Ok(())
+            }
+            None => Err(ChatError::SendError),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send_interval_rejects_rates_out_of_range() {
+        assert_eq!(send_interval(2.0), Some(Duration::from_millis(500)));
+        assert_eq!(send_interval(f64::INFINITY), Some(Duration::ZERO));
+        assert_eq!(send_interval(1e-300), None);
+        assert_eq!(send_interval(0.0), None);
+        assert_eq!(send_interval(-1.0), None);
+        assert_eq!(send_interval(f64::NAN), None);
+    }
+}
@@ -0,0 +1,156 @@
+/// Messages shown in a view of `height` rows, following the newest message unless scrolled up.
+/// Messages can take several rows, `rows` tells how many.
+pub struct Scrollback<T> {
+    items: Vec<T>,
+    /// Number of items between the bottom of the view and the newest item
+    offset: usize,
+    /// Items received while scrolled up
+    unseen: usize,
+}
+
+impl<T> Default for Scrollback<T> {
+    fn default() -> Self {
+        Self {
+            items: Vec::new(),
+            offset: 0,
+            unseen: 0,
+        }
+    }
+}
+
+impl<T> Scrollback<T> {
+    pub fn push(&mut self, item: T) {
+        self.items.push(item);
+        if self.offset > 0 {
+            // Keep the view where it is
+            self.offset += 1;
+            self.unseen += 1;
+        }
+    }
+
+    pub fn scroll_up(&mut self, items: usize, height: usize, rows: impl Fn(&T) -> usize) {
+        self.offset = (self.offset + items).min(self.max_offset(height, rows));
+    }
+
+    pub fn scroll_down(&mut self, items: usize) {
+        self.offset = self.offset.saturating_sub(items);
+        if self.offset == 0 {
+            self.unseen = 0;
+        }
+    }
+
+    pub fn scroll_to_top(&mut self, height: usize, rows: impl Fn(&T) -> usize) {
+        self.offset = self.max_offset(height, rows);
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.offset = 0;
+        self.unseen = 0;
+    }
+
+    /// Items received since scrolling up, zero when following the newest message
+    pub fn unseen(&self) -> usize {
+        self.unseen
+    }
+
+    /// Items filling the view, the oldest may not fit completely
+    pub fn visible(&self, height: usize, rows: impl Fn(&T) -> usize) -> &[T] {
+        let offset = if self.offset > 0 {
+            self.offset.min(self.max_offset(height, &rows))
+        } else {
+            0
+        };
+        let end = self.items.len() - offset;
+        let mut start = end;
+        let mut filled = 0;
+        while start > 0 && filled < height {
+            start -= 1;
+            filled += rows(&self.items[start]);
+        }
+        &self.items[start..end]
+    }
+
+    /// Offset showing the oldest items at the top of the view, leaving out an item that would be
+    /// cut at the bottom unless it is the only one
+    fn max_offset(&self, height: usize, rows: impl Fn(&T) -> usize) -> usize {
+        let mut filled = 0;
+        for (index, item) in self.items.iter().enumerate() {
+            filled += rows(item);
+            if filled >= height {
+                let end = if filled > height && index > 0 {
+                    index
+                } else {
+                    index + 1
+                };
+                return self.items.len() - end;
+            }
+        }
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn one_row(_: &u32) -> usize {
+        1
+    }
+
+    fn scrollback(items: std::ops::Range<u32>) -> Scrollback<u32> {
+        let mut scrollback = Scrollback::default();
+        items.for_each(|item| scrollback.push(item));
+        scrollback
+    }
+
+    #[test]
+    fn follows_the_newest_items() {
+        let mut scrollback = scrollback(0..10);
+        assert_eq!(scrollback.visible(3, one_row), [7, 8, 9]);
+        scrollback.push(10);
+        assert_eq!(scrollback.visible(3, one_row), [8, 9, 10]);
+        assert_eq!(scrollback.unseen(), 0);
+    }
+
+    #[test]
+    fn stays_in_place_while_scrolled_up() {
+        let mut scrollback = scrollback(0..10);
+        scrollback.scroll_up(2, 3, one_row);
+        assert_eq!(scrollback.visible(3, one_row), [5, 6, 7]);
+        scrollback.push(10);
+        assert_eq!(scrollback.visible(3, one_row), [5, 6, 7]);
+        assert_eq!(scrollback.unseen(), 1);
+        scrollback.scroll_down(3);
+        assert_eq!(scrollback.visible(3, one_row), [8, 9, 10]);
+        assert_eq!(scrollback.unseen(), 0);
+    }
+
+    #[test]
+    fn scrolling_stops_at_the_oldest_item() {
+        let mut scrollback = scrollback(0..10);
+        scrollback.scroll_up(100, 3, one_row);
+        assert_eq!(scrollback.visible(3, one_row), [0, 1, 2]);
+        scrollback.scroll_to_bottom();
+        scrollback.scroll_to_top(3, one_row);
+        assert_eq!(scrollback.visible(3, one_row), [0, 1, 2]);
+    }
+
+    #[test]
+    fn items_can_take_several_rows() {
+        let rows = |item: &u32| *item as usize;
+        let mut scrollback = scrollback(1..4);
+        // The oldest visible item may be cut at the top
+        assert_eq!(scrollback.visible(4, rows), [2, 3]);
+        // but scrolling to the top leaves out an item cut at the bottom
+        scrollback.scroll_to_top(4, rows);
+        assert_eq!(scrollback.visible(4, rows), [1, 2]);
+    }
+
+    #[test]
+    fn fewer_items_than_rows() {
+        let mut scrollback = scrollback(0..2);
+        scrollback.scroll_up(1, 5, one_row);
+        assert_eq!(scrollback.visible(5, one_row), [0, 1]);
+        assert!(Scrollback::<u32>::default().visible(5, one_row).is_empty());
+    }
+}
@@ -0,0 +1,76 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Run `command` with `text` on stdin and return the first line it prints, e.g. the URL of an
+/// uploaded paste
+pub fn upload(command: &[String], text: &str) -> anyhow::Result<String> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("No snippet_command configured"))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Write from another thread, the command may fill its output pipes before reading all of stdin
+    let writer = child.stdin.take().map(|mut stdin| {
+        let text = text.to_owned();
+        thread::spawn(move || stdin.write_all(text.as_bytes()))
+    });
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match stderr.trim() {
+            "" => anyhow::bail!("{} failed with {}", program, output.status),
+            stderr => anyhow::bail!("{} failed with {}: {}", program, output.status, stderr),
+        }
+    }
+    if let Some(writer) = writer {
+        writer
+            .join()
+            .map_err(|_| anyhow::anyhow!("Writing to {} panicked", program))??;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_owned)
+        .ok_or_else(|| anyhow::anyhow!("{} printed nothing", program))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Vec<String> {
+        vec!["sh".to_owned(), "-c".to_owned(), script.to_owned()]
+    }
+
+    #[test]
+    fn returns_the_first_printed_line() {
+        let url = upload(
+            &sh("cat >/dev/null; echo; echo ' https://example.com/1 '"),
+            "hi",
+        );
+        assert_eq!(url.unwrap(), "https://example.com/1");
+    }
+
+    #[test]
+    fn large_input_does_not_block_on_output() {
+        // Far more than a pipe buffer both ways
+        let text = "line\n".repeat(100_000);
+        assert_eq!(upload(&sh("cat"), &text).unwrap(), "line");
+    }
+
+    #[test]
+    fn errors_include_stderr() {
+        let e = upload(&sh("echo 'no token' >&2; exit 3"), "hi").unwrap_err();
+        assert!(e
+            .to_string()
+            .ends_with("failed with exit status: 3: no token"));
+        assert!(upload(&sh("cat >/dev/null"), "hi").is_err());
+        assert!(upload(&[], "hi").is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use chatrs::highlight::nick_color;
+use serde::{Deserialize, Deserializer};
+use tui::style::Color;
+
+/// Colors used by the UI
+pub struct Theme {
+    /// The user's own nick
+    pub nick: Color,
+    /// Other nicks, picked by `chatrs::highlight::nick_color`
+    pub nicks: Vec<Color>,
+    pub notice: Color,
+    pub status: Color,
+    pub error: Color,
+    pub mention: Color,
+    pub timestamp: Color,
+}
+
+/// The `[theme]` section of the configuration: a bundled theme and colors overriding it
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    pub name: String,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub nick: Option<Color>,
+    #[serde(deserialize_with = "deserialize_colors")]
+    pub nicks: Option<Vec<Color>>,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub notice: Option<Color>,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub status: Option<Color>,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub error: Option<Color>,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub mention: Option<Color>,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub timestamp: Option<Color>,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            name: "dark".to_owned(),
+            nick: None,
+            nicks: None,
+            notice: None,
+            status: None,
+            error: None,
+            mention: None,
+            timestamp: None,
+        }
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            nick: Color::Magenta,
+            nicks: vec![
+                Color::LightRed,
+                Color::LightGreen,
+                Color::LightYellow,
+                Color::LightBlue,
+                Color::LightMagenta,
+                Color::LightCyan,
+                Color::Green,
+                Color::Cyan,
+            ],
+            notice: Color::Yellow,
+            status: Color::Gray,
+            error: Color::Red,
+            mention: Color::LightRed,
+            timestamp: Color::DarkGray,
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            nick: Color::Magenta,
+            nicks: vec![
+                Color::Red,
+                Color::Green,
+                Color::Blue,
+                Color::Cyan,
+                Color::Indexed(130),
+                Color::Indexed(90),
+                Color::Indexed(22),
+                Color::Indexed(18),
+            ],
+            notice: Color::Blue,
+            status: Color::DarkGray,
+            error: Color::Red,
+            mention: Color::Red,
+            timestamp: Color::Gray,
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Self {
+            nick: Color::LightCyan,
+            nicks: vec![
+                Color::LightRed,
+                Color::LightGreen,
+                Color::LightYellow,
+                Color::LightBlue,
+                Color::LightMagenta,
+                Color::White,
+            ],
+            notice: Color::LightYellow,
+            status: Color::White,
+            error: Color::LightRed,
+            mention: Color::LightMagenta,
+            timestamp: Color::White,
+        }
+    }
+
+    pub fn from_config(config: &ThemeConfig) -> anyhow::Result<Self> {
+        let theme = match config.name.as_str() {
+            "dark" => Self::dark(),
+            "light" => Self::light(),
+            "high-contrast" => Self::high_contrast(),
+            name => anyhow::bail!(
+                "Unknown theme {}, expected dark, light or high-contrast",
+                name
+            ),
+        };
+        if config.nicks.as_ref().is_some_and(Vec::is_empty) {
+            anyhow::bail!("The theme's nicks must list at least one color");
+        }
+        Ok(Self {
+            nick: config.nick.unwrap_or(theme.nick),
+            nicks: config.nicks.clone().unwrap_or(theme.nicks),
+            notice: config.notice.unwrap_or(theme.notice),
+            status: config.status.unwrap_or(theme.status),
+            error: config.error.unwrap_or(theme.error),
+            mention: config.mention.unwrap_or(theme.mention),
+            timestamp: config.timestamp.unwrap_or(theme.timestamp),
+        })
+    }
+
+    /// Color of `nick`, the user's own nick has a color of its own
+    pub fn nick_color(&self, nick: &str, own_nick: &str) -> Color {
+        if nick == own_nick {
+            self.nick
+        } else {
+            self.nicks[nick_color(nick, self.nicks.len())]
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+/// Parse a color name like "lightred", a hex color like "#ff8800" or a 256 color palette index
+fn parse_color(s: &str) -> Option<Color> {
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let rgb = u32::from_str_radix(hex, 16).ok()?;
+        return Some(Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8));
+    }
+    if let Ok(index) = s.parse() {
+        return Some(Color::Indexed(index));
+    }
+    let color = match s.to_lowercase().replace(['-', '_'], "").as_str() {
+        "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    };
+    Some(color)
+}
+
+fn deserialize_color<'de, D>(deserializer: D) -> Result<Option<Color>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse_color(&s)
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid color: {}", s)))
+}
+
+fn deserialize_colors<'de, D>(deserializer: D) -> Result<Option<Vec<Color>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| {
+            parse_color(s).ok_or_else(|| serde::de::Error::custom(format!("invalid color: {}", s)))
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_color_names() {
+        assert_eq!(parse_color("red"), Some(Color::Red));
+        assert_eq!(parse_color("LightRed"), Some(Color::LightRed));
+        assert_eq!(parse_color("dark-grey"), Some(Color::DarkGray));
+        assert_eq!(parse_color("light_blue"), Some(Color::LightBlue));
+        assert_eq!(parse_color("purple"), None);
+    }
+
+    #[test]
+    fn parses_hex_colors_and_palette_indices() {
+        assert_eq!(parse_color("#ff8800"), Some(Color::Rgb(0xff, 0x88, 0x00)));
+        assert_eq!(parse_color("#f80"), None);
+        assert_eq!(parse_color("#gg8800"), None);
+        assert_eq!(parse_color("208"), Some(Color::Indexed(208)));
+        assert_eq!(parse_color("256"), None);
+    }
+}
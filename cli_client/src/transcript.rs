@@ -0,0 +1,287 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chatrs::MessageKind;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::buffer::Line;
+use crate::Message;
+
+/// Server directory used before connecting to a server
+const NO_SERVER: &str = "no-server";
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscriptFormat {
+    /// One human readable line per message, continuation lines of multiline messages are indented
+    Plain,
+    /// One JSON object per line
+    Jsonl,
+}
+
+/// The `[transcript]` section: logging conversations to files
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TranscriptConfig {
+    /// Log from startup, `/log on` and `/log off` change it while running
+    pub enabled: bool,
+    /// Defaults to `chatrs/logs` under the user's data directory
+    pub directory: Option<PathBuf>,
+    pub format: TranscriptFormat,
+    /// Size in bytes after which a log file is rotated
+    pub max_size: u64,
+    /// Rotated files kept per log file, the oldest are removed
+    pub keep: usize,
+}
+
+impl Default for TranscriptConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: None,
+            format: TranscriptFormat::Plain,
+            max_size: 10 * 1024 * 1024,
+            keep: 5,
+        }
+    }
+}
+
+/// Writes every message to `<directory>/<server>/<buffer>.log`, or `.jsonl` in the JSON Lines format
+pub struct Transcript {
+    pub enabled: bool,
+    directory: Option<PathBuf>,
+    format: TranscriptFormat,
+    max_size: u64,
+    keep: usize,
+    server: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    time: String,
+    buffer: &'a str,
+    #[serde(flatten)]
+    message: &'a Message,
+}
+
+impl Transcript {
+    pub fn new(config: &TranscriptConfig) -> Self {
+        let directory = config
+            .directory
+            .clone()
+            .or_else(|| dirs::data_dir().map(|dir| dir.join("chatrs").join("logs")));
+        Self {
+            enabled: config.enabled,
+            directory,
+            format: config.format,
+            max_size: config.max_size,
+            keep: config.keep,
+            server: None,
+        }
+    }
+
+    pub fn directory(&self) -> Option<&Path> {
+        self.directory.as_deref()
+    }
+
+    /// Log following messages under the directory of `server`
+    pub fn set_server(&mut self, server: &str) {
+        self.server = Some(server.to_owned());
+    }
+
+    /// Append `line` to the log of `buffer` if logging is enabled
+    pub fn write(&self, buffer: &str, line: &Line) {
+        if !self.enabled {
+            return;
+        }
+        if let Some(ref directory) = self.directory {
+            let server = self.server.as_deref().unwrap_or(NO_SERVER);
+            let extension = match self.format {
+                TranscriptFormat::Plain => "log",
+                TranscriptFormat::Jsonl => "jsonl",
+            };
+            let path = directory.join(file_name(server)).join(format!(
+                "{}.{}",
+                file_name(buffer),
+                extension
+            ));
+            if let Err(e) = self.append(&path, buffer, line) {
+                warn!(path:% = path.display(), error:% = e; "Could not write transcript");
+            }
+        }
+    }
+
+    fn append(&self, path: &Path, buffer: &str, line: &Line) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.len() >= self.max_size => self.rotate(path)?,
+            _ => {}
+        }
+        let mut record = match self.format {
+            TranscriptFormat::Plain => format_plain(line),
+            TranscriptFormat::Jsonl => serde_json::to_string(&Record {
+                time: line.time.to_rfc3339(),
+                buffer,
+                message: &line.message,
+            })?,
+        };
+        record.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(record.as_bytes())
+    }
+
+    /// Shift `path` to `path.1`, `path.1` to `path.2` and so on, dropping the files beyond `keep`
+    fn rotate(&self, path: &Path) -> io::Result<()> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+        if self.keep == 0 {
+            return fs::remove_file(path);
+        }
+        match fs::remove_file(rotated(self.keep)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        for n in (1..self.keep).rev() {
+            match fs::rename(rotated(n), rotated(n + 1)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        fs::rename(path, rotated(1))
+    }
+}
+
+fn format_plain(line: &Line) -> String {
+    let time = line.time.format("%Y-%m-%d %H:%M:%S");
+    let record = match line.message {
+        Message::Chat {
+            ref nick,
+            kind,
+            ref content,
+            ..
+        } => match kind {
+            MessageKind::Normal => format!("{} <{}> {}", time, nick, content),
+            MessageKind::Action => format!("{} * {} {}", time, nick, content),
+            MessageKind::Notice => format!("{} -{}- {}", time, nick, content),
+            MessageKind::System => format!("{} -!- {}: {}", time, nick, content),
+        },
+        Message::ChangeNick { ref nick } => format!("{} * Changed nick to {}", time, nick),
+        Message::Notice { ref content } => format!("{} -!- {}", time, content),
+        Message::Status { ref content } => format!("{} *** {}", time, content),
+        Message::Error { ref content } => format!("{} !!! {}", time, content),
+    };
+    // Indent the continuation lines of multiline messages to tell them from new messages
+    record.replace('\n', "\n\t")
+}
+
+/// Make a server address or buffer name safe to use as a file name
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '\0' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Local};
+    use std::process;
+
+    fn line(content: &str) -> Line {
+        Line {
+            time: DateTime::parse_from_rfc3339("2024-01-02T03:04:05Z")
+                .unwrap()
+                .with_timezone(&Local),
+            message: Message::Chat {
+                nick: "ferris".to_owned(),
+                kind: MessageKind::Normal,
+                content: content.to_owned(),
+                highlight: false,
+            },
+        }
+    }
+
+    /// A transcript writing to a directory of its own under the temporary directory
+    fn transcript(test: &str, format: TranscriptFormat, max_size: u64, keep: usize) -> Transcript {
+        let directory =
+            std::env::temp_dir().join(format!("chatrs-transcript-{}-{}", test, process::id()));
+        fs::remove_dir_all(&directory).ok();
+        let mut transcript = Transcript::new(&TranscriptConfig {
+            enabled: true,
+            directory: Some(directory),
+            format,
+            max_size,
+            keep,
+        });
+        transcript.set_server("127.0.0.1:3042");
+        transcript
+    }
+
+    fn read(transcript: &Transcript, file: &str) -> Option<String> {
+        let path = transcript
+            .directory()
+            .unwrap()
+            .join("127.0.0.1_3042")
+            .join(file);
+        fs::read_to_string(path).ok()
+    }
+
+    #[test]
+    fn rotates_and_keeps_the_newest_files() {
+        let transcript = transcript("rotate", TranscriptFormat::Plain, 1, 2);
+        for n in 1..=4 {
+            transcript.write("#rust", &line(&n.to_string()));
+        }
+        let ends_with = |file, content| read(&transcript, file).unwrap().ends_with(content);
+        assert!(ends_with("#rust.log", "<ferris> 4\n"));
+        assert!(ends_with("#rust.log.1", "<ferris> 3\n"));
+        assert!(ends_with("#rust.log.2", "<ferris> 2\n"));
+        assert_eq!(read(&transcript, "#rust.log.3"), None);
+        fs::remove_dir_all(transcript.directory().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn appends_below_the_size_limit() {
+        let transcript = transcript("append", TranscriptFormat::Plain, 1024, 2);
+        transcript.write("ferris/bot", &line("one\ntwo"));
+        transcript.write("ferris/bot", &line("three"));
+        let contents = read(&transcript, "ferris_bot.log").unwrap();
+        assert_eq!(contents.lines().count(), 3);
+        assert!(contents.contains("<ferris> one\n\ttwo\n"));
+        assert_eq!(read(&transcript, "ferris_bot.log.1"), None);
+        fs::remove_dir_all(transcript.directory().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        let transcript = transcript("jsonl", TranscriptFormat::Jsonl, 1024, 2);
+        transcript.write("#rust", &line("hi"));
+        let contents = read(&transcript, "#rust.jsonl").unwrap();
+        let record: serde_json::Value = serde_json::from_str(contents.trim_end()).unwrap();
+        assert_eq!(record["buffer"], "#rust");
+        assert_eq!(record["content"], "hi");
+        assert_eq!(record["message_kind"], "normal");
+        fs::remove_dir_all(transcript.directory().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn keeping_no_files_truncates() {
+        let transcript = transcript("keep0", TranscriptFormat::Plain, 1, 0);
+        transcript.write("#rust", &line("one"));
+        transcript.write("#rust", &line("two"));
+        assert!(read(&transcript, "#rust.log")
+            .unwrap()
+            .ends_with("<ferris> two\n"));
+        assert_eq!(read(&transcript, "#rust.log.1"), None);
+        fs::remove_dir_all(transcript.directory().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+use message_io::node::NodeHandler;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use chatrs::admin::AdminRequest;
+use log::{info, warn};
+
+use crate::server::Signal;
+
+/// How long to wait for the event loop to answer an admin request
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a client may take to send its request or read the response
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Accept admin connections on a Unix socket and forward the requests to the event loop
+pub fn serve(path: &Path, handler: NodeHandler<Signal>) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        prepare_dir(dir)?;
+    }
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.file_type().is_socket() => {
+            anyhow::bail!("Admin socket {} exists and is not a socket", path.display());
+        }
+        Ok(_) => {
+            if UnixStream::connect(path).is_ok() {
+                anyhow::bail!("Admin socket {} is already in use", path.display());
+            }
+            // Left behind by a server that did not shut down cleanly
+            fs::remove_file(path)?;
+        }
+        Err(_) => {}
+    }
+    let listener = bind_private(path)?;
+    info!(path:% = path.display(); "Listening for admin connections");
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!(error:% = e; "Admin connection failed");
+                    continue;
+                }
+            };
+            // A connection of its own so a slow client does not hold up the others
+            let handler = handler.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle_connection(stream, &handler) {
+                    warn!(error:% = e; "Admin connection failed");
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Create the socket's directory only accessible to the user, or check that an existing one is
+/// the user's and not writable by others who could replace the socket
+fn prepare_dir(dir: &Path) -> anyhow::Result<()> {
+    if !dir.exists() {
+        fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    }
+    let metadata = fs::metadata(dir)?;
+    // SAFETY: geteuid has no preconditions and can't fail
+    let uid = unsafe { libc::geteuid() };
+    if metadata.uid() != uid || metadata.mode() & 0o022 != 0 {
+        anyhow::bail!(
+            "Admin socket directory {} must be owned by the server's user and not writable by others",
+            dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// Bind the socket with a umask leaving it only accessible to the user, so no one else can
+/// connect before its permissions could be changed
+fn bind_private(path: &Path) -> anyhow::Result<UnixListener> {
+    // SAFETY: umask has no preconditions and can't fail. It is process wide, but the server
+    // creates no other files while starting up.
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(path);
+    unsafe { libc::umask(umask) };
+    Ok(listener?)
+}
+
+fn handle_connection(mut stream: UnixStream, handler: &NodeHandler<Signal>) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut data = Vec::new();
+    stream.read_to_end(&mut data)?;
+    let request = AdminRequest::deserialize(&data)?;
+
+    let (reply, response) = mpsc::channel();
+    handler.signals().send(Signal::Admin { request, reply });
+    let response = response.recv_timeout(REPLY_TIMEOUT)?;
+
+    stream.write_all(&response.serialize()?)?;
+    Ok(())
+}
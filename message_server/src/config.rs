@@ -0,0 +1,145 @@
+use message_io::network::Transport;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chatrs::admin;
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub listeners: Vec<Listener>,
+    pub metrics_address: String,
+    pub admin_socket: PathBuf,
+    pub motd: String,
+    /// Seconds clients should wait before reconnecting after a shutdown
+    pub reconnect_after: Option<u64>,
+    pub rate_limit: Option<RateLimit>,
+    pub banned_words: Vec<String>,
+    /// Nicks only clients made operators through the admin socket can take
+    pub operators: Vec<String>,
+}
+
+#[derive(Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Listener {
+    pub transport: Transport,
+    pub address: String,
+}
+
+/// Allow at most `messages` chat messages per client within `seconds`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub messages: usize,
+    pub seconds: u64,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    fn parse(contents: &str) -> anyhow::Result<Self> {
+        let config: Self = toml::from_str(contents)?;
+        if let Some(ref rate_limit) = config.rate_limit {
+            if rate_limit.messages == 0 || rate_limit.seconds == 0 {
+                anyhow::bail!("rate_limit.messages and rate_limit.seconds must be above zero");
+            }
+        }
+        Ok(config)
+    }
+
+    /// Names of the settings changed in `other` that only take effect after a restart
+    pub fn restart_required(&self, other: &Config) -> Vec<String> {
+        let mut changed = Vec::new();
+        if self.listeners != other.listeners {
+            changed.push("listeners".to_owned());
+        }
+        if self.metrics_address != other.metrics_address {
+            changed.push("metrics_address".to_owned());
+        }
+        if self.admin_socket != other.admin_socket {
+            changed.push("admin_socket".to_owned());
+        }
+        changed
+    }
+
+    /// Take the settings from `other` that can be changed while the server is running
+    pub fn apply(&mut self, other: Config) {
+        self.motd = other.motd;
+        self.reconnect_after = other.reconnect_after;
+        self.rate_limit = other.rate_limit;
+        self.banned_words = other.banned_words;
+        self.operators = other.operators;
+    }
+
+    pub fn is_operator_nick(&self, nick: &str) -> bool {
+        self.operators.iter().any(|operator| operator == nick)
+    }
+
+    pub fn reconnect_after(&self) -> Option<Duration> {
+        self.reconnect_after.map(Duration::from_secs)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listeners: vec![
+                Listener {
+                    transport: Transport::FramedTcp,
+                    address: "0.0.0.0:3042".to_owned(),
+                },
+                Listener {
+                    transport: Transport::Udp,
+                    address: "0.0.0.0:3043".to_owned(),
+                },
+                Listener {
+                    transport: Transport::Ws,
+                    address: "0.0.0.0:3044".to_owned(),
+                },
+            ],
+            metrics_address: "0.0.0.0:3045".to_owned(),
+            admin_socket: admin::default_socket_path(),
+            motd: String::new(),
+            reconnect_after: None,
+            rate_limit: None,
+            banned_words: Vec::new(),
+            operators: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_rate_limits_are_rejected() {
+        assert!(Config::parse("[rate_limit]\nmessages = 0\nseconds = 10").is_err());
+        assert!(Config::parse("[rate_limit]\nmessages = 5\nseconds = 0").is_err());
+        assert!(Config::parse("[rate_limit]\nmessages = 5\nseconds = 10").is_ok());
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        assert!(Config::parse("operator = [\"ferris\"]").is_err());
+        assert!(Config::parse("[rate_limit]\nmessage = 5\nseconds = 10").is_err());
+    }
+
+    #[test]
+    fn listener_changes_require_a_restart() {
+        let config = Config::default();
+        let mut other = Config::parse("motd = \"hi\"").unwrap();
+        assert!(config.restart_required(&other).is_empty());
+        other.listeners.pop();
+        assert_eq!(config.restart_required(&other), ["listeners"]);
+        other.metrics_address = "127.0.0.1:9000".to_owned();
+        assert_eq!(
+            config.restart_required(&other),
+            ["listeners", "metrics_address"]
+        );
+    }
+}
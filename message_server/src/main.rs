@@ -1,69 +1,83 @@
-use message_io::network::{NetEvent, Transport};
-use message_io::node::{self};
-use std::collections::HashMap;
+use message_io::node::{self, NodeEvent, NodeHandler};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::thread;
 
-use anyhow;
-use chatrs::{ClientMessage, ServerMessage};
+use chatrs::logging::{self, LogFormat};
+use log::{info, warn};
 
-struct Client {
-    nick: String,
-}
+mod admin;
+mod config;
+mod metrics;
+mod server;
+
+use config::Config;
+use metrics::Metrics;
+use server::{Server, Signal};
 
 fn main() -> anyhow::Result<()> {
-    let (handler, listener) = node::split::<()>();
+    logging::builder("info", LogFormat::from_env()?).init();
 
-    handler
-        .network()
-        .listen(Transport::FramedTcp, "0.0.0.0:3042")?;
-    handler.network().listen(Transport::Udp, "0.0.0.0:3043")?;
-    handler.network().listen(Transport::Ws, "0.0.0.0:3044")?;
+    let config_path = env::args_os().nth(1).map(PathBuf::from);
+    let config = match config_path {
+        Some(ref path) => Config::load(path)?,
+        None => Config::default(),
+    };
 
-    let mut clients = HashMap::new();
+    let (handler, listener) = node::split::<Signal>();
 
-    listener.for_each(move |event| match event.network() {
-        NetEvent::Connected(endpoint, _) => {
-            clients.insert(
-                endpoint,
-                Client {
-                    nick: "anonymous".to_owned(),
-                },
-            );
-            println!("Client connected");
-        }
-        NetEvent::Message(endpoint, data) => {
-            if let Ok(client_message) = ClientMessage::deserialize(&data) {
-                match client_message {
-                    ClientMessage::Message { content } => {
-                        let nick = if let Some(client) = clients.get(&endpoint) {
-                            client.nick.clone()
-                        } else {
-                            "unknown".to_owned()
-                        };
-                        let message = ServerMessage::Message { nick, content };
-                        if let Ok(data) = message.serialize() {
-                            for client in clients.keys() {
-                                handler.network().send(*client, &data);
-                            }
-                        } else {
-                            eprintln!("ERROR: a serialization error occurred");
-                        }
-                    }
+    for listener in config.listeners.iter() {
+        handler
+            .network()
+            .listen(listener.transport, &listener.address)?;
+        info!(transport:% = listener.transport, address = listener.address.as_str(); "Listening");
+    }
+
+    let metrics = Arc::new(Metrics::new()?);
+    metrics::serve(&config.metrics_address, metrics.clone())?;
+    info!(address = config.metrics_address.as_str(); "Serving metrics");
+
+    admin::serve(&config.admin_socket, handler.clone())?;
+    handle_signals(handler.clone())?;
+
+    let admin_socket = config.admin_socket.clone();
+    let mut server = Server::new(handler, metrics, config, config_path);
+    listener.for_each(move |event| match event {
+        NodeEvent::Signal(signal) => server.handle_signal(signal),
+        NodeEvent::Network(net_event) => server.handle_network(net_event),
+    });
+    fs::remove_file(&admin_socket).ok();
+    info!("Server stopped");
+
+    Ok(())
+}
 
-                    ClientMessage::Nick { nick } => {
-                        if let Some(client) = clients.get_mut(&endpoint) {
-                            client.nick = nick;
-                        }
-                    }
-                };
+/// Reload the configuration on SIGHUP. Shut down gracefully on the first SIGINT or SIGTERM
+/// and exit immediately on the second.
+fn handle_signals(handler: NodeHandler<Signal>) -> anyhow::Result<()> {
+    let mut signals = Signals::new([SIGHUP, SIGINT, SIGTERM])?;
+    thread::spawn(move || {
+        let mut terminating = false;
+        for signal in signals.forever() {
+            if signal == SIGHUP {
+                info!(signal; "Received reload signal");
+                handler.signals().send(Signal::Reload);
+            } else if !terminating {
+                info!(signal; "Received termination signal");
+                terminating = true;
+                handler.signals().send(Signal::Shutdown {
+                    reason: "Server is shutting down".to_owned(),
+                });
             } else {
-                eprintln!("ERROR: a deserialization error occurred");
+                warn!(signal; "Received second termination signal, exiting immediately");
+                process::exit(1);
             }
         }
-        NetEvent::Disconnected(endpoint) => {
-            clients.remove(&endpoint);
-            println!("Client disconnected");
-        }
     });
-
     Ok(())
 }
@@ -0,0 +1,200 @@
+use log::warn;
+use message_io::network::Transport;
+use prometheus::core::Collector;
+use prometheus::{
+    exponential_buckets, Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGaugeVec,
+    Opts, Registry, TextEncoder,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tiny_http::{Header, Response, Server};
+
+use chatrs::admin::ServerStats;
+
+/// How old the last event loop heartbeat may be before `/healthz` reports failure
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Metrics {
+    registry: Registry,
+    connected_clients: IntGaugeVec,
+    messages_received: IntCounterVec,
+    messages_sent: IntCounterVec,
+    bytes_received: IntCounterVec,
+    bytes_sent: IntCounterVec,
+    deserialization_errors: IntCounter,
+    rate_limit_hits: IntCounter,
+    broadcast_latency: Histogram,
+    last_heartbeat: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> prometheus::Result<Self> {
+        let connected_clients = IntGaugeVec::new(
+            Opts::new("chatrs_connected_clients", "Number of connected clients"),
+            &["transport"],
+        )?;
+        let messages_received = IntCounterVec::new(
+            Opts::new(
+                "chatrs_messages_received_total",
+                "Messages received from clients",
+            ),
+            &["transport"],
+        )?;
+        let messages_sent = IntCounterVec::new(
+            Opts::new("chatrs_messages_sent_total", "Messages sent to clients"),
+            &["transport"],
+        )?;
+        let bytes_received = IntCounterVec::new(
+            Opts::new("chatrs_bytes_received_total", "Bytes received from clients"),
+            &["transport"],
+        )?;
+        let bytes_sent = IntCounterVec::new(
+            Opts::new("chatrs_bytes_sent_total", "Bytes sent to clients"),
+            &["transport"],
+        )?;
+        let deserialization_errors = IntCounter::new(
+            "chatrs_deserialization_errors_total",
+            "Client messages that could not be deserialized",
+        )?;
+        let rate_limit_hits = IntCounter::new(
+            "chatrs_rate_limit_hits_total",
+            "Client messages dropped by the rate limit",
+        )?;
+        let broadcast_latency = Histogram::with_opts(
+            HistogramOpts::new(
+                "chatrs_broadcast_latency_seconds",
+                "Time spent broadcasting a message to all clients",
+            )
+            .buckets(exponential_buckets(0.000_01, 4.0, 10)?),
+        )?;
+
+        let registry = Registry::new();
+        registry.register(Box::new(connected_clients.clone()))?;
+        registry.register(Box::new(messages_received.clone()))?;
+        registry.register(Box::new(messages_sent.clone()))?;
+        registry.register(Box::new(bytes_received.clone()))?;
+        registry.register(Box::new(bytes_sent.clone()))?;
+        registry.register(Box::new(deserialization_errors.clone()))?;
+        registry.register(Box::new(rate_limit_hits.clone()))?;
+        registry.register(Box::new(broadcast_latency.clone()))?;
+
+        Ok(Self {
+            registry,
+            connected_clients,
+            messages_received,
+            messages_sent,
+            bytes_received,
+            bytes_sent,
+            deserialization_errors,
+            rate_limit_hits,
+            broadcast_latency,
+            last_heartbeat: AtomicU64::new(now_millis()),
+        })
+    }
+
+    pub fn client_connected(&self, transport: Transport) {
+        self.connected_clients
+            .with_label_values(&[&transport.to_string()])
+            .inc();
+    }
+    pub fn client_disconnected(&self, transport: Transport) {
+        self.connected_clients
+            .with_label_values(&[&transport.to_string()])
+            .dec();
+    }
+    pub fn message_received(&self, transport: Transport, bytes: usize) {
+        let label = transport.to_string();
+        self.messages_received.with_label_values(&[&label]).inc();
+        self.bytes_received
+            .with_label_values(&[&label])
+            .inc_by(bytes as u64);
+    }
+    pub fn message_sent(&self, transport: Transport, bytes: usize) {
+        let label = transport.to_string();
+        self.messages_sent.with_label_values(&[&label]).inc();
+        self.bytes_sent
+            .with_label_values(&[&label])
+            .inc_by(bytes as u64);
+    }
+    pub fn deserialization_error(&self) {
+        self.deserialization_errors.inc();
+    }
+    pub fn rate_limit_hit(&self) {
+        self.rate_limit_hits.inc();
+    }
+    pub fn broadcast_finished(&self, duration: Duration) {
+        self.broadcast_latency.observe(duration.as_secs_f64());
+    }
+
+    /// Called periodically from the event loop to show it is still processing events
+    pub fn heartbeat(&self) {
+        self.last_heartbeat.store(now_millis(), Ordering::Relaxed);
+    }
+    pub fn is_healthy(&self) -> bool {
+        let age = now_millis().saturating_sub(self.last_heartbeat.load(Ordering::Relaxed));
+        age <= HEALTH_TIMEOUT.as_millis() as u64
+    }
+
+    pub fn stats(&self, uptime: Duration, clients: usize) -> ServerStats {
+        ServerStats {
+            uptime,
+            clients: clients as u64,
+            messages_received: counter_total(&self.messages_received),
+            messages_sent: counter_total(&self.messages_sent),
+            bytes_received: counter_total(&self.bytes_received),
+            bytes_sent: counter_total(&self.bytes_sent),
+            deserialization_errors: self.deserialization_errors.get(),
+            rate_limit_hits: self.rate_limit_hits.get(),
+        }
+    }
+
+    fn encode(&self) -> prometheus::Result<(Vec<u8>, String)> {
+        let encoder = TextEncoder::new();
+        let mut buffer = Vec::new();
+        encoder.encode(&self.registry.gather(), &mut buffer)?;
+        Ok((buffer, encoder.format_type().to_owned()))
+    }
+}
+
+/// Serve `/metrics` and `/healthz` over HTTP from a background thread
+pub fn serve(address: &str, metrics: Arc<Metrics>) -> anyhow::Result<thread::JoinHandle<()>> {
+    let server = Server::http(address).map_err(|e| anyhow::anyhow!(e))?;
+    Ok(thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = match request.url() {
+                "/metrics" => match metrics.encode() {
+                    Ok((body, content_type)) => Response::from_data(body).with_header(
+                        Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes())
+                            .expect("Content type is a valid header value"),
+                    ),
+                    Err(e) => Response::from_string(e.to_string()).with_status_code(500),
+                },
+                "/healthz" if metrics.is_healthy() => Response::from_string("ok"),
+                "/healthz" => Response::from_string("event loop stalled").with_status_code(503),
+                _ => Response::from_string("not found").with_status_code(404),
+            };
+            if let Err(e) = request.respond(response) {
+                warn!(error:% = e; "Could not respond to HTTP request");
+            }
+        }
+    }))
+}
+
+/// Sum of a counter over all its label values
+fn counter_total(counter: &IntCounterVec) -> u64 {
+    counter
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .map(|metric| metric.get_counter().get_value() as u64)
+        .sum()
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
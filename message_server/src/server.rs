@@ -0,0 +1,993 @@
+use message_io::network::{Endpoint, NetEvent, Transport};
+use message_io::node::NodeHandler;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant, SystemTime};
+
+use chatrs::admin::{AdminRequest, AdminResponse, ClientInfo, ClientSelector};
+use chatrs::{
+    is_channel_name, ChannelInfo, ClientMessage, Member, MessageKind, ServerMessage, Topic,
+    UserInfo, DEFAULT_NICK,
+};
+use log::{debug, error, info, warn};
+
+use crate::config::{Config, RateLimit};
+use crate::metrics::Metrics;
+
+const TRANSPORTS: [Transport; 4] = [
+    Transport::Tcp,
+    Transport::FramedTcp,
+    Transport::Udp,
+    Transport::Ws,
+];
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+pub enum Signal {
+    Heartbeat,
+    Shutdown {
+        reason: String,
+    },
+    Reload,
+    Admin {
+        request: AdminRequest,
+        reply: mpsc::Sender<AdminResponse>,
+    },
+}
+
+struct Client {
+    id: u64,
+    nick: String,
+    transport: Transport,
+    connected_since: SystemTime,
+    recent_messages: VecDeque<Instant>,
+    /// When the client last sent a chat message or connected, for telling how long it has been idle
+    last_active: Instant,
+    /// Reason given when going away, None when not away
+    away: Option<String>,
+    /// Made an operator through the admin socket
+    operator: bool,
+}
+
+impl Client {
+    /// Record a chat message unless it would exceed the rate limit
+    fn allow_message(&mut self, limit: &RateLimit, now: Instant) -> bool {
+        let window = Duration::from_secs(limit.seconds);
+        while let Some(sent) = self.recent_messages.front() {
+            if now.duration_since(*sent) < window {
+                break;
+            }
+            self.recent_messages.pop_front();
+        }
+        if self.recent_messages.len() < limit.messages {
+            self.recent_messages.push_back(now);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+pub struct Server {
+    handler: NodeHandler<Signal>,
+    metrics: Arc<Metrics>,
+    clients: HashMap<Endpoint, Client>,
+    /// Members of each channel, channels are removed when the last member leaves
+    channels: HashMap<String, HashSet<Endpoint>>,
+    /// Topics of channels that have one, removed with the channel
+    topics: HashMap<String, Topic>,
+    next_client_id: u64,
+    next_message_id: u64,
+    started: Instant,
+    config: Config,
+    config_path: Option<PathBuf>,
+}
+
+impl Server {
+    pub fn new(
+        handler: NodeHandler<Signal>,
+        metrics: Arc<Metrics>,
+        config: Config,
+        config_path: Option<PathBuf>,
+    ) -> Self {
+        handler.signals().send(Signal::Heartbeat);
+        Self {
+            handler,
+            metrics,
+            clients: HashMap::new(),
+            channels: HashMap::new(),
+            topics: HashMap::new(),
+            next_client_id: 0,
+            next_message_id: 0,
+            started: Instant::now(),
+            config,
+            config_path,
+        }
+    }
+
+    pub fn handle_signal(&mut self, signal: Signal) {
+        match signal {
+            Signal::Heartbeat => {
+                self.metrics.heartbeat();
+                self.handler
+                    .signals()
+                    .send_with_timer(Signal::Heartbeat, HEARTBEAT_INTERVAL);
+            }
+            Signal::Shutdown { reason } => self.shutdown(reason),
+            Signal::Reload => {
+                if let Err(e) = self.reload() {
+                    error!(error:% = e; "Could not reload configuration");
+                }
+            }
+            Signal::Admin { request, reply } => {
+                let response = self.admin(request);
+                // The admin connection may have timed out waiting for us
+                reply.send(response).ok();
+            }
+        }
+    }
+
+    pub fn handle_network(&mut self, event: NetEvent) {
+        match event {
+            NetEvent::Connected(endpoint, _) => self.client_connected(endpoint),
+            NetEvent::Message(endpoint, data) => {
+                self.metrics
+                    .message_received(transport_of(endpoint), data.len());
+                if let Ok(client_message) = ClientMessage::deserialize(data) {
+                    self.client_message(endpoint, client_message);
+                } else {
+                    self.metrics.deserialization_error();
+                    warn!(endpoint:% = endpoint, bytes = data.len(); "Could not deserialize client message");
+                }
+            }
+            NetEvent::Disconnected(endpoint) => self.client_disconnected(endpoint),
+        }
+    }
+
+    fn client_connected(&mut self, endpoint: Endpoint) {
+        let transport = transport_of(endpoint);
+        let id = self.next_client_id;
+        self.next_client_id += 1;
+        self.clients.insert(
+            endpoint,
+            Client {
+                id,
+                nick: DEFAULT_NICK.to_owned(),
+                transport,
+                connected_since: SystemTime::now(),
+                recent_messages: VecDeque::new(),
+                last_active: Instant::now(),
+                away: None,
+                operator: false,
+            },
+        );
+        self.metrics.client_connected(transport);
+        info!(endpoint:% = endpoint, transport:% = transport, client_id = id; "Client connected");
+
+        if !self.config.motd.is_empty() {
+            self.notice(endpoint, self.config.motd.clone());
+        }
+    }
+
+    fn client_message(&mut self, endpoint: Endpoint, message: ClientMessage) {
+        match message {
+            ClientMessage::Message { kind, content } => {
+                if let Some((message_id, nick, content)) =
+                    self.accept_message(endpoint, kind, content)
+                {
+                    self.broadcast(&ServerMessage::Message {
+                        nick,
+                        kind,
+                        content,
+                    });
+                    debug!(message_id, recipients = self.clients.len(); "Message broadcast");
+                }
+            }
+
+            ClientMessage::ChannelMessage {
+                channel,
+                kind,
+                content,
+            } => {
+                let members: Vec<Endpoint> = match self.channels.get(&channel) {
+                    Some(members) if members.contains(&endpoint) => {
+                        members.iter().copied().collect()
+                    }
+                    _ => {
+                        self.notice(endpoint, format!("You are not on {}", channel));
+                        return;
+                    }
+                };
+                if let Some((message_id, nick, content)) =
+                    self.accept_message(endpoint, kind, content)
+                {
+                    let message = ServerMessage::ChannelMessage {
+                        channel,
+                        nick,
+                        kind,
+                        content,
+                    };
+                    self.send_all(&members, &message);
+                    debug!(message_id, recipients = members.len(); "Message sent to channel");
+                }
+            }
+
+            ClientMessage::PrivateMessage {
+                nick: to,
+                kind,
+                content,
+            } => {
+                let recipient = match self.endpoint_of(&to) {
+                    Some(recipient) => recipient,
+                    None => {
+                        self.notice(endpoint, format!("No such nick: {}", to));
+                        return;
+                    }
+                };
+                if let Some((message_id, from, content)) =
+                    self.accept_message(endpoint, kind, content)
+                {
+                    // Notices are not meant to be replied to automatically
+                    let away = self
+                        .clients
+                        .get(&recipient)
+                        .and_then(|client| client.away.clone())
+                        .filter(|_| recipient != endpoint && kind != MessageKind::Notice);
+                    // Echo the message so the sender sees it in the conversation
+                    let mut recipients = vec![recipient];
+                    if recipient != endpoint {
+                        recipients.push(endpoint);
+                    }
+                    let message = ServerMessage::PrivateMessage {
+                        from,
+                        to: to.clone(),
+                        kind,
+                        content,
+                    };
+                    self.send_all(&recipients, &message);
+                    debug!(message_id; "Private message sent");
+                    if let Some(reason) = away {
+                        self.notice(endpoint, format!("{} is away: {}", to, reason));
+                    }
+                }
+            }
+
+            ClientMessage::Join { channel } => {
+                if !is_channel_name(&channel) {
+                    self.notice(endpoint, format!("Invalid channel name: {}", channel));
+                    return;
+                }
+                let members = self.channels.entry(channel.clone()).or_default();
+                if !members.insert(endpoint) {
+                    return;
+                }
+                let members: Vec<Endpoint> = members.iter().copied().collect();
+                let nick = self.nick_of(endpoint);
+                info!(endpoint:% = endpoint, nick = nick.as_str(), channel = channel.as_str(); "Joined channel");
+                let joined = ServerMessage::Joined {
+                    channel: channel.clone(),
+                    nick,
+                };
+                self.send_all(&members, &joined);
+                self.send_members(&channel);
+                if let Some(topic) = self.topics.get(&channel) {
+                    let message = ServerMessage::Topic {
+                        channel,
+                        topic: Some(topic.clone()),
+                    };
+                    self.send(endpoint, &message);
+                }
+            }
+
+            ClientMessage::SetTopic { channel, topic } => {
+                let members: Vec<Endpoint> = match self.channels.get(&channel) {
+                    Some(members) if members.contains(&endpoint) => {
+                        members.iter().copied().collect()
+                    }
+                    _ => {
+                        self.notice(endpoint, format!("You are not on {}", channel));
+                        return;
+                    }
+                };
+                let topic = if topic.is_empty() {
+                    self.topics.remove(&channel);
+                    None
+                } else {
+                    // Topics go through the same rate limit and banned words as messages
+                    let (_, set_by, text) =
+                        match self.accept_message(endpoint, MessageKind::Normal, topic) {
+                            Some(accepted) => accepted,
+                            None => return,
+                        };
+                    let topic = Topic {
+                        text,
+                        set_by,
+                        set_at: SystemTime::now(),
+                    };
+                    self.topics.insert(channel.clone(), topic.clone());
+                    Some(topic)
+                };
+                info!(
+                    endpoint:% = endpoint, channel = channel.as_str(),
+                    removed = topic.is_none();
+                    "Topic changed"
+                );
+                self.send_all(&members, &ServerMessage::Topic { channel, topic });
+            }
+
+            ClientMessage::ListUsers { channel } => {
+                let endpoints: Vec<Endpoint> = match channel {
+                    Some(ref channel) => match self.channels.get(channel) {
+                        Some(members) => members.iter().copied().collect(),
+                        None => {
+                            self.notice(endpoint, format!("No such channel: {}", channel));
+                            return;
+                        }
+                    },
+                    None => self.clients.keys().copied().collect(),
+                };
+                let now = Instant::now();
+                let mut users: Vec<UserInfo> = endpoints
+                    .iter()
+                    .filter_map(|endpoint| self.clients.get(endpoint))
+                    .map(|client| UserInfo {
+                        nick: client.nick.clone(),
+                        operator: client.operator,
+                        idle: now.duration_since(client.last_active),
+                        away: client.away.clone(),
+                    })
+                    .collect();
+                users.sort_by(|a, b| a.nick.cmp(&b.nick));
+                self.send(endpoint, &ServerMessage::UserList { channel, users });
+            }
+
+            ClientMessage::ListChannels => {
+                let mut channels: Vec<ChannelInfo> = self
+                    .channels
+                    .iter()
+                    .map(|(name, members)| ChannelInfo {
+                        name: name.clone(),
+                        members: members.len(),
+                        topic: self.topics.get(name).cloned(),
+                    })
+                    .collect();
+                channels.sort_by(|a, b| a.name.cmp(&b.name));
+                self.send(endpoint, &ServerMessage::ChannelList { channels });
+            }
+
+            ClientMessage::Away { reason } => {
+                let reason = reason
+                    .filter(|reason| !reason.trim().is_empty())
+                    .map(|reason| censor(&reason, &self.config.banned_words));
+                if let Some(client) = self.clients.get_mut(&endpoint) {
+                    info!(
+                        endpoint:% = endpoint, nick = client.nick.as_str(),
+                        away = reason.is_some();
+                        "Away changed"
+                    );
+                    client.away = reason;
+                }
+                for channel in self.channels_of(endpoint) {
+                    self.send_members(&channel);
+                }
+            }
+
+            ClientMessage::Ping { token } => self.send(endpoint, &ServerMessage::Pong { token }),
+
+            ClientMessage::Part { channel } => self.part(endpoint, &channel),
+
+            ClientMessage::Nick { nick } => {
+                if let Err(reason) = self.check_nick(endpoint, &nick) {
+                    let current = self.nick_of(endpoint);
+                    self.notice(endpoint, format!("{}, you are still {}", reason, current));
+                    return;
+                }
+                if let Some(client) = self.clients.get_mut(&endpoint) {
+                    info!(
+                        endpoint:% = endpoint, old_nick = client.nick.as_str(),
+                        nick = nick.as_str();
+                        "Nick changed"
+                    );
+                    client.nick = nick;
+                }
+                for channel in self.channels_of(endpoint) {
+                    self.send_members(&channel);
+                }
+            }
+        }
+    }
+
+    /// Apply the rate limit and banned words to a chat message, returns the message id, the
+    /// sender's nick and the content to send if the message is allowed. Clients can't send system
+    /// messages.
+    fn accept_message(
+        &mut self,
+        endpoint: Endpoint,
+        kind: MessageKind,
+        content: String,
+    ) -> Option<(u64, String, String)> {
+        if kind == MessageKind::System {
+            self.notice(
+                endpoint,
+                "Only the server can send system messages".to_owned(),
+            );
+            return None;
+        }
+        let message_id = self.next_message_id;
+        self.next_message_id += 1;
+        let now = Instant::now();
+        let (nick, allowed) = match self.clients.get_mut(&endpoint) {
+            Some(client) => {
+                let allowed = client.operator
+                    || match self.config.rate_limit {
+                        Some(ref limit) => client.allow_message(limit, now),
+                        None => true,
+                    };
+                if allowed {
+                    client.last_active = now;
+                }
+                (client.nick.clone(), allowed)
+            }
+            None => ("unknown".to_owned(), true),
+        };
+        debug!(
+            endpoint:% = endpoint, nick = nick.as_str(), message_id;
+            "Message received"
+        );
+        if !allowed {
+            self.metrics.rate_limit_hit();
+            debug!(endpoint:% = endpoint, message_id; "Message rate limited");
+            self.notice(endpoint, "You are sending messages too fast".to_owned());
+            return None;
+        }
+        let content = censor(&content, &self.config.banned_words);
+        Some((message_id, nick, content))
+    }
+
+    fn part(&mut self, endpoint: Endpoint, channel: &str) {
+        let members = match self.channels.get_mut(channel) {
+            Some(members) => members,
+            None => return,
+        };
+        if !members.remove(&endpoint) {
+            return;
+        }
+        let members: Vec<Endpoint> = members.iter().copied().collect();
+        if members.is_empty() {
+            self.channels.remove(channel);
+            self.topics.remove(channel);
+        }
+        let nick = self.nick_of(endpoint);
+        info!(endpoint:% = endpoint, nick = nick.as_str(), channel; "Parted channel");
+        let parted = ServerMessage::Parted {
+            channel: channel.to_owned(),
+            nick,
+        };
+        self.send_all(&members, &parted);
+        self.send_members(channel);
+    }
+
+    /// Send the member list of `channel` to its members
+    fn send_members(&self, channel: &str) {
+        let endpoints: Vec<Endpoint> = match self.channels.get(channel) {
+            Some(members) => members.iter().copied().collect(),
+            None => return,
+        };
+        let mut members: Vec<Member> = endpoints
+            .iter()
+            .filter_map(|endpoint| self.clients.get(endpoint))
+            .map(|client| Member {
+                nick: client.nick.clone(),
+                operator: client.operator,
+                away: client.away.is_some(),
+            })
+            .collect();
+        members.sort_by(|a, b| a.nick.cmp(&b.nick));
+        let message = ServerMessage::Members {
+            channel: channel.to_owned(),
+            members,
+        };
+        self.send_all(&endpoints, &message);
+    }
+
+    fn channels_of(&self, endpoint: Endpoint) -> Vec<String> {
+        self.channels
+            .iter()
+            .filter(|(_, members)| members.contains(&endpoint))
+            .map(|(channel, _)| channel.clone())
+            .collect()
+    }
+
+    fn client_disconnected(&mut self, endpoint: Endpoint) {
+        for channel in self.channels_of(endpoint) {
+            self.part(endpoint, &channel);
+        }
+        if let Some(client) = self.clients.remove(&endpoint) {
+            self.metrics.client_disconnected(client.transport);
+            info!(endpoint:% = endpoint, nick = client.nick.as_str(); "Client disconnected");
+        }
+    }
+
+    /// Why `endpoint` can't change its nick to `nick`, if it can't
+    fn check_nick(&self, endpoint: Endpoint, nick: &str) -> Result<(), String> {
+        if nick.is_empty() || nick.contains(char::is_whitespace) {
+            return Err("Nicks can't be empty or contain whitespace".to_owned());
+        }
+        if nick.starts_with('#') {
+            return Err(format!("Nick {} looks like a channel", nick));
+        }
+        if nick == DEFAULT_NICK {
+            return Err(format!("Nick {} is for clients without a nick", nick));
+        }
+        let operator = self
+            .clients
+            .get(&endpoint)
+            .is_some_and(|client| client.operator);
+        if self.config.is_operator_nick(nick) && !operator {
+            return Err(format!("Nick {} is reserved for operators", nick));
+        }
+        if censor(nick, &self.config.banned_words) != nick {
+            return Err(format!("Nick {} is not allowed", nick));
+        }
+        match self.endpoint_of(nick) {
+            Some(other) if other != endpoint => Err(format!("Nick {} is taken", nick)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The client using `nick`, nicks are unique apart from the default nick
+    fn endpoint_of(&self, nick: &str) -> Option<Endpoint> {
+        if nick == DEFAULT_NICK {
+            return None;
+        }
+        self.clients
+            .iter()
+            .find(|(_, client)| client.nick == nick)
+            .map(|(endpoint, _)| *endpoint)
+    }
+
+    fn select(&self, selector: &ClientSelector) -> Vec<Endpoint> {
+        self.clients
+            .iter()
+            .filter(|(_, client)| match selector {
+                ClientSelector::Id(id) => client.id == *id,
+                ClientSelector::Nick(nick) => client.nick == *nick,
+            })
+            .map(|(endpoint, _)| *endpoint)
+            .collect()
+    }
+
+    fn nick_of(&self, endpoint: Endpoint) -> String {
+        self.clients
+            .get(&endpoint)
+            .map_or("unknown", |client| client.nick.as_str())
+            .to_owned()
+    }
+
+    fn admin(&mut self, request: AdminRequest) -> AdminResponse {
+        match request {
+            AdminRequest::ListClients => {
+                let mut clients: Vec<ClientInfo> = self
+                    .clients
+                    .iter()
+                    .map(|(endpoint, client)| ClientInfo {
+                        id: client.id,
+                        endpoint: endpoint.addr().to_string(),
+                        transport: client.transport.to_string(),
+                        nick: client.nick.clone(),
+                        operator: client.operator,
+                        connected_since: client.connected_since,
+                    })
+                    .collect();
+                clients.sort_by_key(|client| client.id);
+                AdminResponse::Clients { clients }
+            }
+            AdminRequest::Kick { client } => {
+                let endpoints = self.select(&client);
+                let kicked = ServerMessage::Notice {
+                    content: "You have been kicked from the server".to_owned(),
+                };
+                for endpoint in endpoints.iter() {
+                    self.send(*endpoint, &kicked);
+                    self.handler.network().remove(endpoint.resource_id());
+                    self.client_disconnected(*endpoint);
+                }
+                AdminResponse::Kicked {
+                    count: endpoints.len(),
+                }
+            }
+            AdminRequest::SetOperator { client, operator } => {
+                let endpoints = self.select(&client);
+                for endpoint in endpoints.iter() {
+                    if let Some(client) = self.clients.get_mut(endpoint) {
+                        info!(
+                            endpoint:% = endpoint, nick = client.nick.as_str(), operator;
+                            "Operator status changed"
+                        );
+                        client.operator = operator;
+                    }
+                    let content = if operator {
+                        "You are now an operator"
+                    } else {
+                        "You are no longer an operator"
+                    };
+                    self.notice(*endpoint, content.to_owned());
+                    for channel in self.channels_of(*endpoint) {
+                        self.send_members(&channel);
+                    }
+                }
+                AdminResponse::OperatorChanged {
+                    count: endpoints.len(),
+                }
+            }
+            AdminRequest::Notice { content } => {
+                info!(content = content.as_str(); "Broadcasting notice");
+                self.broadcast(&ServerMessage::Notice { content });
+                AdminResponse::Done
+            }
+            AdminRequest::SetMotd { motd } => {
+                info!(motd = motd.as_str(); "MOTD changed");
+                self.config.motd = motd;
+                AdminResponse::Done
+            }
+            AdminRequest::ReloadConfig => match self.reload() {
+                Ok(restart_required) => AdminResponse::Reloaded { restart_required },
+                Err(e) => AdminResponse::Error {
+                    message: e.to_string(),
+                },
+            },
+            AdminRequest::Stats => AdminResponse::Stats {
+                stats: self
+                    .metrics
+                    .stats(self.started.elapsed(), self.clients.len()),
+            },
+        }
+    }
+
+    /// Apply the settings that can change at runtime and return the ones needing a restart
+    fn reload(&mut self) -> anyhow::Result<Vec<String>> {
+        let path = self
+            .config_path
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Server was started without a configuration file"))?;
+        let config = Config::load(path)?;
+        let restart_required = self.config.restart_required(&config);
+        for setting in restart_required.iter() {
+            warn!(setting = setting.as_str(); "Setting changed but requires a restart to apply");
+        }
+        self.config.apply(config);
+        info!(path:% = path.display(); "Configuration reloaded");
+        Ok(restart_required)
+    }
+
+    fn send(&self, endpoint: Endpoint, message: &ServerMessage) {
+        match message.serialize() {
+            Ok(data) => {
+                self.handler.network().send(endpoint, &data);
+                self.metrics
+                    .message_sent(transport_of(endpoint), data.len());
+            }
+            Err(e) => error!(error:% = e; "Could not serialize message"),
+        }
+    }
+
+    fn notice(&self, endpoint: Endpoint, content: String) {
+        self.send(endpoint, &ServerMessage::Notice { content });
+    }
+
+    fn broadcast(&self, message: &ServerMessage) {
+        let endpoints: Vec<Endpoint> = self.clients.keys().copied().collect();
+        self.send_all(&endpoints, message);
+    }
+
+    fn send_all(&self, endpoints: &[Endpoint], message: &ServerMessage) {
+        let data = match message.serialize() {
+            Ok(data) => data,
+            Err(e) => {
+                error!(error:% = e; "Could not serialize message");
+                return;
+            }
+        };
+        let start = Instant::now();
+        for endpoint in endpoints {
+            self.handler.network().send(*endpoint, &data);
+            self.metrics
+                .message_sent(transport_of(*endpoint), data.len());
+        }
+        self.metrics.broadcast_finished(start.elapsed());
+    }
+
+    /// Tell clients why the server is going away and stop the event loop
+    fn shutdown(&mut self, reason: String) {
+        info!(reason = reason.as_str(); "Shutting down");
+        let message = ServerMessage::ServerShutdown {
+            reason,
+            reconnect_after: self.config.reconnect_after(),
+        };
+        self.broadcast(&message);
+        self.handler.stop();
+    }
+}
+
+/// Mask banned words with asterisks, matching whole words case-insensitively
+fn censor(content: &str, banned_words: &[String]) -> String {
+    let mut censored = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find(char::is_alphanumeric) {
+        censored.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !c.is_alphanumeric())
+            .unwrap_or(rest.len());
+        let word = &rest[..end];
+        let word_lowercase = word.to_lowercase();
+        if banned_words
+            .iter()
+            .any(|banned| banned.to_lowercase() == word_lowercase)
+        {
+            censored.extend(word.chars().map(|_| '*'));
+        } else {
+            censored.push_str(word);
+        }
+        rest = &rest[end..];
+    }
+    censored.push_str(rest);
+    censored
+}
+
+fn transport_of(endpoint: Endpoint) -> Transport {
+    let adapter_id = endpoint.resource_id().adapter_id();
+    TRANSPORTS
+        .iter()
+        .copied()
+        .find(|transport| transport.id() == adapter_id)
+        .expect("Endpoints only come from known transports")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message_io::node::{self, NodeListener};
+    use std::net::UdpSocket;
+
+    /// A server with clients connected over UDP, each reading what the server sends it from a
+    /// socket of its own
+    struct TestServer {
+        server: Server,
+        clients: Vec<(Endpoint, UdpSocket)>,
+        // Keeps the network running
+        _listener: NodeListener<Signal>,
+    }
+
+    impl TestServer {
+        fn new() -> Self {
+            let (handler, listener) = node::split();
+            let metrics = Arc::new(Metrics::new().unwrap());
+            Self {
+                server: Server::new(handler, metrics, Config::default(), None),
+                clients: Vec::new(),
+                _listener: listener,
+            }
+        }
+
+        /// Connect a client with `nick`, returns its index
+        fn connect(&mut self, nick: &str) -> usize {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            socket
+                .set_read_timeout(Some(Duration::from_millis(50)))
+                .unwrap();
+            let (endpoint, _) = self
+                .server
+                .handler
+                .network()
+                .connect(Transport::Udp, socket.local_addr().unwrap())
+                .unwrap();
+            self.server.client_connected(endpoint);
+            self.clients.push((endpoint, socket));
+            let client = self.clients.len() - 1;
+            self.send(
+                client,
+                ClientMessage::Nick {
+                    nick: nick.to_owned(),
+                },
+            );
+            self.received(client);
+            client
+        }
+
+        fn send(&mut self, client: usize, message: ClientMessage) {
+            let endpoint = self.clients[client].0;
+            self.server.client_message(endpoint, message);
+        }
+
+        /// Messages sent to `client` since the last call
+        fn received(&self, client: usize) -> Vec<ServerMessage> {
+            let mut buffer = [0; 65536];
+            let mut messages = Vec::new();
+            while let Ok(len) = self.clients[client].1.recv(&mut buffer) {
+                messages.push(ServerMessage::deserialize(&buffer[..len]).unwrap());
+            }
+            messages
+        }
+
+        fn join(&mut self, client: usize, channel: &str) {
+            let channel = channel.to_owned();
+            self.send(client, ClientMessage::Join { channel });
+        }
+    }
+
+    fn topic_of(messages: &[ServerMessage]) -> Option<Option<&str>> {
+        messages.iter().find_map(|message| match message {
+            ServerMessage::Topic { topic, .. } => Some(topic.as_ref().map(|t| t.text.as_str())),
+            _ => None,
+        })
+    }
+
+    fn client() -> Client {
+        Client {
+            id: 0,
+            nick: "ferris".to_owned(),
+            transport: Transport::FramedTcp,
+            connected_since: SystemTime::now(),
+            recent_messages: VecDeque::new(),
+            last_active: Instant::now(),
+            away: None,
+            operator: false,
+        }
+    }
+
+    #[test]
+    fn censors_whole_words_ignoring_case() {
+        let banned = vec!["darn".to_owned(), "ÄRGER".to_owned()];
+        assert_eq!(censor("Darn it, DARN!", &banned), "**** it, ****!");
+        assert_eq!(censor("darning undarn", &banned), "darning undarn");
+        assert_eq!(censor("so ein Ärger", &banned), "so ein *****");
+        assert_eq!(censor("ärgerlich", &banned), "ärgerlich");
+        assert_eq!(censor("", &banned), "");
+        assert_eq!(censor("darn", &[]), "darn");
+    }
+
+    #[test]
+    fn rate_limit_window_slides() {
+        let limit = RateLimit {
+            messages: 2,
+            seconds: 10,
+        };
+        let window = Duration::from_secs(10);
+        let start = Instant::now();
+        let mut client = client();
+        assert!(client.allow_message(&limit, start));
+        assert!(client.allow_message(&limit, start + Duration::from_secs(1)));
+        assert!(!client.allow_message(&limit, start + Duration::from_secs(2)));
+        // The first message leaves the window exactly one window after it was sent
+        assert!(!client.allow_message(&limit, start + window - Duration::from_nanos(1)));
+        assert!(client.allow_message(&limit, start + window));
+        assert!(!client.allow_message(&limit, start + window));
+        assert!(client.allow_message(&limit, start + window + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn topics_are_set_cleared_and_sent_on_join() {
+        let mut test = TestServer::new();
+        let ferris = test.connect("ferris");
+        let corro = test.connect("corro");
+        test.join(ferris, "#rust");
+        test.received(ferris);
+
+        let set_topic = |topic: &str| ClientMessage::SetTopic {
+            channel: "#rust".to_owned(),
+            topic: topic.to_owned(),
+        };
+        // Only members can set the topic
+        test.send(corro, set_topic("not a member"));
+        assert!(matches!(
+            test.received(corro)[..],
+            [ServerMessage::Notice { .. }]
+        ));
+        test.send(ferris, set_topic("Rust things"));
+        assert_eq!(topic_of(&test.received(ferris)), Some(Some("Rust things")));
+
+        test.join(corro, "#rust");
+        let received = test.received(corro);
+        assert_eq!(topic_of(&received), Some(Some("Rust things")));
+        if let Some(ServerMessage::Topic {
+            topic: Some(topic), ..
+        }) = received.last()
+        {
+            assert_eq!(topic.set_by, "ferris");
+        } else {
+            panic!("Topic not sent last on join");
+        }
+        test.received(ferris);
+
+        test.send(corro, set_topic(""));
+        assert_eq!(topic_of(&test.received(ferris)), Some(None));
+        assert_eq!(topic_of(&test.received(corro)), Some(None));
+        test.send(
+            ferris,
+            ClientMessage::Part {
+                channel: "#rust".to_owned(),
+            },
+        );
+        test.join(ferris, "#rust");
+        assert_eq!(topic_of(&test.received(ferris)), None);
+    }
+
+    fn private_message(nick: &str, kind: MessageKind) -> ClientMessage {
+        ClientMessage::PrivateMessage {
+            nick: nick.to_owned(),
+            kind,
+            content: "hi".to_owned(),
+        }
+    }
+
+    fn away(reason: Option<&str>) -> ClientMessage {
+        ClientMessage::Away {
+            reason: reason.map(str::to_owned),
+        }
+    }
+
+    fn notices(messages: &[ServerMessage]) -> Vec<&str> {
+        messages
+            .iter()
+            .filter_map(|message| match message {
+                ServerMessage::Notice { content } => Some(content.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn away_replies_go_to_others_and_not_for_notices() {
+        let mut test = TestServer::new();
+        let ferris = test.connect("ferris");
+        let corro = test.connect("corro");
+        test.send(ferris, away(Some("lunch")));
+
+        test.send(corro, private_message("ferris", MessageKind::Normal));
+        assert_eq!(notices(&test.received(corro)), ["ferris is away: lunch"]);
+        test.send(corro, private_message("ferris", MessageKind::Notice));
+        assert!(notices(&test.received(corro)).is_empty());
+        // Messaging yourself while away gets no reply
+        test.send(ferris, private_message("ferris", MessageKind::Normal));
+        assert!(notices(&test.received(ferris)).is_empty());
+
+        test.send(ferris, away(None));
+        test.send(corro, private_message("ferris", MessageKind::Normal));
+        assert!(notices(&test.received(corro)).is_empty());
+    }
+
+    #[test]
+    fn away_changes_update_members() {
+        let mut test = TestServer::new();
+        let ferris = test.connect("ferris");
+        let corro = test.connect("corro");
+        test.join(ferris, "#rust");
+        test.join(corro, "#rust");
+        test.received(corro);
+
+        let away_members = |messages: Vec<ServerMessage>| {
+            messages
+                .into_iter()
+                .rev()
+                .find_map(|message| match message {
+                    ServerMessage::Members { members, .. } => Some(
+                        members
+                            .into_iter()
+                            .filter(|member| member.away)
+                            .map(|member| member.nick)
+                            .collect::<Vec<_>>(),
+                    ),
+                    _ => None,
+                })
+        };
+        test.send(ferris, away(Some("lunch")));
+        assert_eq!(
+            away_members(test.received(corro)),
+            Some(vec!["ferris".to_owned()])
+        );
+        // A blank reason is the same as coming back
+        test.send(ferris, away(Some(" ")));
+        assert_eq!(away_members(test.received(corro)), Some(vec![]));
+    }
+}
@@ -0,0 +1,88 @@
+use serde::{Serialize, Deserialize};
+use std::env;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Where `message_server` listens for admin connections unless configured otherwise:
+/// `$XDG_RUNTIME_DIR/chatrs-admin.sock`, or a socket in a directory of the user's own under the
+/// temporary directory when that is unset
+pub fn default_socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("chatrs-admin.sock"),
+        None => {
+            let user = env::var("USER").unwrap_or_default();
+            env::temp_dir().join(format!("chatrs-{}", user)).join("admin.sock")
+        }
+    }
+}
+
+/// Sent over the admin socket, one request per connection
+#[derive(Serialize, Deserialize)]
+pub enum AdminRequest {
+    ListClients,
+    Kick { client: ClientSelector },
+    /// Grant or revoke operator status, which exempts from the rate limit and allows taking the
+    /// nicks reserved for operators
+    SetOperator { client: ClientSelector, operator: bool },
+    Notice { content: String },
+    SetMotd { motd: String },
+    ReloadConfig,
+    Stats,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum AdminResponse {
+    Clients { clients: Vec<ClientInfo> },
+    Kicked { count: usize },
+    OperatorChanged { count: usize },
+    Reloaded { restart_required: Vec<String> },
+    Stats { stats: ServerStats },
+    Done,
+    Error { message: String },
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum ClientSelector {
+    Id(u64),
+    Nick(String),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub endpoint: String,
+    pub transport: String,
+    pub nick: String,
+    pub operator: bool,
+    pub connected_since: SystemTime,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ServerStats {
+    pub uptime: Duration,
+    pub clients: u64,
+    pub messages_received: u64,
+    pub messages_sent: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub deserialization_errors: u64,
+    pub rate_limit_hits: u64,
+}
+
+impl AdminRequest {
+    pub fn serialize(&self) -> bincode::Result<Vec<u8>> {
+        bincode::serialize(self)
+    }
+    pub fn deserialize(bytes: &[u8]) -> bincode::Result<Self> {
+        bincode::deserialize(bytes)
+    }
+}
+
+impl AdminResponse {
+    pub fn serialize(&self) -> bincode::Result<Vec<u8>> {
+        bincode::serialize(self)
+    }
+    pub fn deserialize(bytes: &[u8]) -> bincode::Result<Self> {
+        bincode::deserialize(bytes)
+    }
+}
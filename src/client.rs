@@ -1,10 +1,222 @@
-use crate::{ServerMessage, ClientMessage};
-use std::str::FromStr;
+use crate::{ServerMessage, ClientMessage, ChannelInfo, Member, MessageKind, Topic, UserInfo, is_channel_name};
+use crate::command::{expand_alias, split_command, Arg, ArgType, CommandSpec};
+use std::collections::BTreeMap;
+use std::time::Duration;
 use thiserror::Error;
 
+/// Commands handled by `ChatClientCommon::handle_command`
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "/alias",
+        args: &[Arg { name: "name", kind: ArgType::Word }, Arg { name: "expansion", kind: ArgType::Rest }],
+        min_args: 0,
+        help: "Define a command expanding to ;-separated commands using $1..$9 and $*, or list the aliases"
+    },
+    CommandSpec {
+        name: "/away",
+        args: &[Arg { name: "reason", kind: ArgType::Rest }],
+        min_args: 0,
+        help: "Mark yourself away, private messages are answered with the reason"
+    },
+    CommandSpec { name: "/back", args: &[], min_args: 0, help: "Mark yourself no longer away" },
+    CommandSpec {
+        name: "/connect",
+        args: &[Arg { name: "address", kind: ArgType::Word }],
+        min_args: 1,
+        help: "Connect to a server"
+    },
+    CommandSpec { name: "/disconnect", args: &[], min_args: 0, help: "Disconnect from the server" },
+    CommandSpec {
+        name: "/help",
+        args: &[Arg { name: "command", kind: ArgType::Word }],
+        min_args: 0,
+        help: "List the commands or describe one"
+    },
+    CommandSpec {
+        name: "/join",
+        args: &[Arg { name: "channel", kind: ArgType::Channel }],
+        min_args: 1,
+        help: "Join a channel"
+    },
+    CommandSpec { name: "/list", args: &[], min_args: 0, help: "List the channels with their user counts and topics" },
+    CommandSpec {
+        name: "/me",
+        args: &[Arg { name: "action", kind: ArgType::Rest }],
+        min_args: 1,
+        help: "Send an action, shown as \"* nick action\""
+    },
+    CommandSpec {
+        name: "/msg",
+        args: &[Arg { name: "target", kind: ArgType::Word }, Arg { name: "text", kind: ArgType::Rest }],
+        min_args: 2,
+        help: "Send a message to a nick or a channel"
+    },
+    CommandSpec {
+        name: "/nick",
+        args: &[Arg { name: "nick", kind: ArgType::Word }],
+        min_args: 1,
+        help: "Change your nick"
+    },
+    CommandSpec {
+        name: "/part",
+        args: &[Arg { name: "channel", kind: ArgType::Channel }],
+        min_args: 0,
+        help: "Leave a channel, the current one by default"
+    },
+    CommandSpec { name: "/quit", args: &[], min_args: 0, help: "Quit the client" },
+    CommandSpec {
+        name: "/topic",
+        args: &[Arg { name: "topic", kind: ArgType::Rest }],
+        min_args: 0,
+        help: "Show or set the topic of the current channel, or of the channel given first. -delete removes it."
+    },
+    CommandSpec {
+        name: "/unalias",
+        args: &[Arg { name: "name", kind: ArgType::Word }],
+        min_args: 1,
+        help: "Remove an alias"
+    },
+    CommandSpec {
+        name: "/who",
+        args: &[Arg { name: "channel", kind: ArgType::Channel }],
+        min_args: 0,
+        help: "List the users on the server or on a channel with their idle times"
+    }
+];
+
+/// Reason of `/away` without one
+const DEFAULT_AWAY_REASON: &str = "Away";
+
+/// Aliases used by aliases are expanded up to this depth, deeper ones likely use themselves
+const MAX_ALIAS_DEPTH: usize = 8;
+
+/// Commands a client understands: `COMMANDS`, the ones its UI registers and handles in
+/// `ChatUserInterface::handle_ui_command`, and the user's aliases
+pub struct CommandRegistry {
+    commands: Vec<&'static CommandSpec>,
+    /// Expansions by alias name without the leading slash
+    aliases: BTreeMap<String, String>
+}
+
+impl CommandRegistry {
+    /// Add the UI's own commands, their names must differ from the registered ones
+    pub fn register(&mut self, commands: &'static [CommandSpec]) {
+        for spec in commands.iter() {
+            debug_assert!(self.find(spec.name).is_none(), "{} registered twice", spec.name);
+            self.commands.push(spec);
+        }
+        self.commands.sort_by_key(|spec| spec.name);
+    }
+    /// The spec of a command, the leading slash is optional
+    pub fn find(&self, name: &str) -> Option<&'static CommandSpec> {
+        let name = name.strip_prefix('/').unwrap_or(name);
+        self.commands.iter().copied().find(|spec| &spec.name[1..] == name)
+    }
+    /// Command and alias names with the leading slash, commands first in alphabetical order
+    pub fn names(&self) -> Vec<String> {
+        let commands = self.commands.iter().map(|spec| spec.name.to_owned());
+        commands.chain(self.aliases.keys().map(|name| format!("/{}", name))).collect()
+    }
+    /// Usage and help of every command, one line each with the help texts aligned, and the aliases
+    pub fn help(&self) -> String {
+        let usages: Vec<String> = self.commands.iter().map(|spec| spec.usage()).collect();
+        let width = usages.iter().map(String::len).max().unwrap_or(0);
+        let lines: Vec<String> = self.commands.iter().zip(usages)
+            .map(|(spec, usage)| format!("{:width$}  {}", usage, spec.help, width = width))
+            .collect();
+        let mut help = format!("Commands:\n{}", lines.join("\n"));
+        if !self.aliases.is_empty() {
+            help.push('\n');
+            help.push_str(&self.alias_list());
+        }
+        help
+    }
+    /// The expansion of an alias, the leading slash is optional
+    pub fn alias(&self, name: &str) -> Option<&str> {
+        self.aliases.get(name.strip_prefix('/').unwrap_or(name)).map(String::as_str)
+    }
+    /// Aliases by name without the leading slash
+    pub fn aliases(&self) -> &BTreeMap<String, String> {
+        &self.aliases
+    }
+    /// Define or replace an alias, commands can't be replaced
+    pub fn set_alias(&mut self, name: &str, expansion: &str) -> Result<(), String> {
+        let name = name.strip_prefix('/').unwrap_or(name);
+        if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '/') {
+            return Err(format!("invalid alias name {}", name));
+        }
+        if self.find(name).is_some() {
+            return Err(format!("/{} is a command", name));
+        }
+        if expansion.trim().is_empty() {
+            return Err("expansion can't be empty".to_owned());
+        }
+        if expansion.contains('\n') {
+            return Err("expansion must be a single line".to_owned());
+        }
+        self.aliases.insert(name.to_owned(), expansion.trim().to_owned());
+        Ok(())
+    }
+    /// Remove an alias, returns false if there was none
+    pub fn remove_alias(&mut self, name: &str) -> bool {
+        self.aliases.remove(name.strip_prefix('/').unwrap_or(name)).is_some()
+    }
+    fn alias_list(&self) -> String {
+        let width = self.aliases.keys().map(|name| name.len() + 1).max().unwrap_or(0);
+        let lines: Vec<String> = self.aliases.iter()
+            .map(|(name, expansion)| format!("{:width$}  {}", format!("/{}", name), expansion, width = width))
+            .collect();
+        format!("Aliases:\n{}", lines.join("\n"))
+    }
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        Self { commands: COMMANDS.iter().collect(), aliases: BTreeMap::new() }
+    }
+}
+
+/// Where plain text input is sent
+#[derive(Clone, PartialEq)]
+pub enum Target {
+    Server,
+    Channel(String),
+    Nick(String)
+}
+
 pub trait ChatUserInterface {
-    fn receive_message(&mut self, nick: String, content: String);
+    fn receive_message(&mut self, nick: String, kind: MessageKind, content: String);
+    fn receive_notice(&mut self, content: String);
+    fn receive_channel_message(&mut self, channel: String, nick: String, kind: MessageKind, content: String);
+    fn receive_private_message(&mut self, from: String, to: String, kind: MessageKind, content: String);
+    fn user_joined(&mut self, channel: String, nick: String);
+    fn user_parted(&mut self, channel: String, nick: String);
+    fn channel_members(&mut self, channel: String, members: Vec<Member>);
+    /// The topic of a channel was received on joining it or changed, None when it was removed
+    fn channel_topic(&mut self, channel: String, topic: Option<Topic>);
+    /// Show the last topic received for a channel, asked for with `/topic`
+    fn show_topic(&mut self, channel: String);
+    /// Users on a channel or on the server, asked for with `/who`
+    fn user_list(&mut self, channel: Option<String>, users: Vec<UserInfo>);
+    /// Channels on the server, asked for with `/list`
+    fn channel_list(&mut self, channels: Vec<ChannelInfo>);
+    fn receive_pong(&mut self, token: u64);
     fn change_nick(&mut self, nick: String);
+    /// The user went away with a reason or came back with None
+    fn change_away(&mut self, reason: Option<String>);
+    fn join_channel(&mut self, channel: String);
+    fn part_channel(&mut self, channel: String);
+    fn current_target(&self) -> Target;
+    /// Commands the user can give, including the UI's own
+    fn commands(&self) -> &CommandRegistry;
+    fn commands_mut(&mut self) -> &mut CommandRegistry;
+    /// An alias was defined or removed, save `commands().aliases()` to keep them
+    fn aliases_changed(&mut self);
+    /// Handle a command the UI registered, the parameters have been checked against `spec`
+    fn handle_ui_command(&mut self, spec: &'static CommandSpec, params: Vec<String>) -> ChatResult<()>;
+    /// Show information the user asked for, like `/help`
+    fn show_info(&mut self, content: String);
+    fn server_shutdown(&mut self, reason: String, reconnect_after: Option<Duration>);
     fn quit(&mut self);
 }
 
@@ -18,6 +230,7 @@ pub trait ChatClient {
 pub trait ChatClientCommon {
     fn send(&mut self, message: ClientMessage) -> ChatResult<()>;
     fn send_message(&mut self, content: String) -> ChatResult<()>;
+    fn send_message_to(&mut self, target: Target, kind: MessageKind, content: String) -> ChatResult<()>;
     fn recv(&mut self, message: ServerMessage) -> ChatResult<()>;
     fn recv_binary(&mut self, data: &[u8]) -> ChatResult<()>;
     fn handle_command(&mut self, name: String, params: Vec<String>) -> ChatResult<()>;
@@ -32,8 +245,10 @@ pub enum ChatError {
     SendError,
     #[error("Unknown command: {name}")]
     UnknownCommand { name: String },
-    #[error("Invalid parameters")]
-    InvalidParameters,
+    #[error("Invalid parameters: {reason}. Usage: {usage}")]
+    InvalidParameters { reason: String, usage: String },
+    #[error("Could not expand alias {name}: {reason}")]
+    InvalidAlias { name: String, reason: String },
     #[error("Invalid address: {address}")]
     InvalidAddress { address: String },
     #[error("Error connecting to server")]
@@ -52,18 +267,17 @@ pub enum ParsedInput {
     Empty
 }
 
-impl FromStr for ParsedInput {
-    type Err = ();
-    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        match s.chars().next() {
-            Some('/') => {
-                let mut parts = s.split_whitespace();
-                let name = parts.next().unwrap().to_owned(); // Always succeeds
-                let params = parts.map(str::to_owned).collect();
-                Ok(Self::Command { name, params })
-            },
-            Some(_) => Ok(Self::Message { content: s.to_owned() }),
-            None => Ok(Self::Empty)
+impl ParsedInput {
+    /// Parse a message or a command in `commands` with its parameters
+    pub fn parse(s: &str, commands: &CommandRegistry) -> ChatResult<Self> {
+        match split_command(s) {
+            Some((name, args)) => {
+                let spec = commands.find(name).ok_or_else(|| ChatError::UnknownCommand { name: name.to_owned() })?;
+                let params = spec.parse(args)?;
+                Ok(Self::Command { name: name.to_owned(), params })
+            },
+            None if s.is_empty() => Ok(Self::Empty),
+            None => Ok(Self::Message { content: s.to_owned() })
         }
     }
 }
@@ -75,7 +289,18 @@ impl<T> ChatClientCommon for T where T: ChatClient + ChatUserInterface {
     }
     fn recv(&mut self, message: ServerMessage) -> ChatResult<()> {
         match message {
-            ServerMessage::Message { nick, content } => self.receive_message(nick, content),
+            ServerMessage::Message { nick, kind, content } => self.receive_message(nick, kind, content),
+            ServerMessage::Notice { content } => self.receive_notice(content),
+            ServerMessage::ServerShutdown { reason, reconnect_after } => self.server_shutdown(reason, reconnect_after),
+            ServerMessage::ChannelMessage { channel, nick, kind, content } => self.receive_channel_message(channel, nick, kind, content),
+            ServerMessage::PrivateMessage { from, to, kind, content } => self.receive_private_message(from, to, kind, content),
+            ServerMessage::Joined { channel, nick } => self.user_joined(channel, nick),
+            ServerMessage::Parted { channel, nick } => self.user_parted(channel, nick),
+            ServerMessage::Members { channel, members } => self.channel_members(channel, members),
+            ServerMessage::Topic { channel, topic } => self.channel_topic(channel, topic),
+            ServerMessage::UserList { channel, users } => self.user_list(channel, users),
+            ServerMessage::ChannelList { channels } => self.channel_list(channels),
+            ServerMessage::Pong { token } => self.receive_pong(token),
         };
         Ok(())
     }
@@ -84,41 +309,191 @@ impl<T> ChatClientCommon for T where T: ChatClient + ChatUserInterface {
         self.recv(message)
     }
     fn handle_command(&mut self, name: String, params: Vec<String>) -> ChatResult<()> {
-        match name.as_str() {
-            "/nick" => match params.as_slice() {
-                [nick] => {
-                    self.change_nick(nick.clone());
-                    self.send(ClientMessage::Nick { nick: nick.clone() })
+        let spec = self.commands().find(&name).ok_or_else(|| ChatError::UnknownCommand { name: name.clone() })?;
+        spec.check(&params)?;
+        // The parameters match the spec from here on
+        match (spec.name, params.as_slice()) {
+            ("/help", []) => {
+                let help = self.commands().help();
+                self.show_info(help);
+                Ok(())
+            },
+            ("/help", [command]) => {
+                let name = format!("/{}", command.trim_start_matches('/'));
+                let command = self.commands().find(&name).ok_or(ChatError::UnknownCommand { name })?;
+                self.show_info(format!("{}  {}", command.usage(), command.help));
+                Ok(())
+            },
+            ("/nick", [nick]) => {
+                self.change_nick(nick.clone());
+                self.send(ClientMessage::Nick { nick: nick.clone() })
+            },
+            ("/alias", []) if self.commands().aliases().is_empty() => {
+                self.show_info("No aliases, define one with /alias name expansion".to_owned());
+                Ok(())
+            },
+            ("/alias", []) => {
+                let aliases = self.commands().alias_list();
+                self.show_info(aliases);
+                Ok(())
+            },
+            ("/alias", [name]) => {
+                let expansion = self.commands().alias(name).ok_or_else(|| spec.invalid(format!("no alias {}", name)))?;
+                self.show_info(format!("/{}  {}", name.trim_start_matches('/'), expansion));
+                Ok(())
+            },
+            ("/alias", [name, expansion]) => {
+                self.commands_mut().set_alias(name, expansion).map_err(|reason| spec.invalid(reason))?;
+                self.aliases_changed();
+                self.show_info(format!("Alias /{} defined", name.trim_start_matches('/')));
+                Ok(())
+            },
+            ("/unalias", [name]) => {
+                if !self.commands_mut().remove_alias(name) {
+                    return Err(spec.invalid(format!("no alias {}", name)));
+                }
+                self.aliases_changed();
+                self.show_info(format!("Alias /{} removed", name.trim_start_matches('/')));
+                Ok(())
+            },
+            ("/away", params) => {
+                let reason = params.first().cloned().unwrap_or_else(|| DEFAULT_AWAY_REASON.to_owned());
+                self.change_away(Some(reason.clone()));
+                self.send(ClientMessage::Away { reason: Some(reason) })
+            },
+            ("/back", []) => {
+                self.change_away(None);
+                self.send(ClientMessage::Away { reason: None })
+            },
+            ("/join", [channel]) => {
+                self.join_channel(channel.clone());
+                self.send(ClientMessage::Join { channel: channel.clone() })
+            },
+            ("/part", params) => {
+                let channel = match (params, self.current_target()) {
+                    ([channel], _) => channel.clone(),
+                    (_, Target::Channel(channel)) => channel,
+                    _ => return Err(spec.invalid("missing channel, the current conversation is not a channel"))
+                };
+                self.part_channel(channel.clone());
+                self.send(ClientMessage::Part { channel })
+            },
+            ("/topic", params) => {
+                let input = params.first().map_or("", String::as_str);
+                let (first, after) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+                let (channel, topic) = match self.current_target() {
+                    _ if is_channel_name(first) => (first.to_owned(), after.trim_start()),
+                    Target::Channel(channel) => (channel, input),
+                    _ => return Err(spec.invalid("missing channel, the current conversation is not a channel"))
+                };
+                match topic {
+                    "" => {
+                        self.show_topic(channel);
+                        Ok(())
+                    },
+                    "-delete" => self.send(ClientMessage::SetTopic { channel, topic: String::new() }),
+                    topic => self.send(ClientMessage::SetTopic { channel, topic: topic.to_owned() })
                 }
-                _ => return Err(ChatError::InvalidParameters)
             },
-            "/connect" => match params.as_slice() {
-                [address] => self.connect(address.clone()),
-                _ => Err(ChatError::InvalidParameters),
+            ("/who", params) => self.send(ClientMessage::ListUsers { channel: params.first().cloned() }),
+            ("/list", []) => self.send(ClientMessage::ListChannels),
+            ("/msg", [target, content]) => {
+                let (content, kind) = (content.clone(), MessageKind::Normal);
+                if is_channel_name(target) {
+                    self.send(ClientMessage::ChannelMessage { channel: target.clone(), kind, content })
+                } else {
+                    self.send(ClientMessage::PrivateMessage { nick: target.clone(), kind, content })
+                }
             },
-            "/disconnect" => match params.as_slice() {
-                [] => Ok(self.disconnect()),
-                _ => Err(ChatError::InvalidParameters),
+            ("/me", [action]) => self.send_message_to(self.current_target(), MessageKind::Action, action.clone()),
+            ("/connect", [address]) => self.connect(address.clone()),
+            ("/disconnect", []) => {
+                self.disconnect();
+                Ok(())
             },
-            "/quit" => match params.as_slice() {
-                [] => Ok(self.quit()),
-                _ => Err(ChatError::InvalidParameters),
+            ("/quit", []) => {
+                self.quit();
+                Ok(())
             },
-            _ => Err(ChatError::UnknownCommand { name })
+            _ => self.handle_ui_command(spec, params)
         }
     }
     fn handle_input(&mut self, input: String) -> ChatResult<()> {
-        match input.parse().expect("Parsing input should never fail") {
-            ParsedInput::Command { name, params } => self.handle_command(name, params),
-            ParsedInput::Message { content } => self.send_message(content),
-            ParsedInput::Empty => Ok(())
-        }
+        handle_input_at_depth(self, input, 0)
     }
     fn send_message(&mut self, content: String) -> ChatResult<()> {
+        self.send_message_to(self.current_target(), MessageKind::Normal, content)
+    }
+    fn send_message_to(&mut self, target: Target, kind: MessageKind, content: String) -> ChatResult<()> {
         if self.is_connected() {
-            self.send(ClientMessage::Message { content })
+            let message = match target {
+                Target::Server => ClientMessage::Message { kind, content },
+                Target::Channel(channel) => ClientMessage::ChannelMessage { channel, kind, content },
+                Target::Nick(nick) => ClientMessage::PrivateMessage { nick, kind, content }
+            };
+            self.send(message)
         } else {
             Err(ChatError::SendError)
         }
     }
 }
+
+/// Idle time in its largest two units, e.g. "42s", "5m 3s" or "2d 4h"
+pub fn format_idle(idle: Duration) -> String {
+    let seconds = idle.as_secs();
+    let units = [(seconds / 86400, "d"), (seconds / 3600 % 24, "h"), (seconds / 60 % 60, "m"), (seconds % 60, "s")];
+    let first = units.iter().position(|(amount, _)| *amount > 0).unwrap_or(units.len() - 1);
+    units[first..].iter().take(2)
+        .filter(|(amount, _)| *amount > 0 || first == units.len() - 1)
+        .map(|(amount, unit)| format!("{}{}", amount, unit))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Handle input, expanding aliases used by aliases `depth` levels deep
+fn handle_input_at_depth<T>(client: &mut T, input: String, depth: usize) -> ChatResult<()> where T: ChatClient + ChatUserInterface {
+    if let Some((name, args)) = split_command(&input) {
+        if let Some(expansion) = client.commands().alias(name) {
+            let invalid = |reason: String| ChatError::InvalidAlias { name: name.to_owned(), reason };
+            if depth >= MAX_ALIAS_DEPTH {
+                return Err(invalid("aliases are nested too deeply, does it use itself?".to_owned()));
+            }
+            let lines = expand_alias(expansion, args).map_err(invalid)?;
+            return lines.into_iter().try_for_each(|line| handle_input_at_depth(client, line, depth + 1));
+        }
+    }
+    match ParsedInput::parse(&input, client.commands())? {
+        ParsedInput::Command { name, params } => client.handle_command(name, params),
+        ParsedInput::Message { content } => client.send_message(content),
+        ParsedInput::Empty => Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn idle(seconds: u64) -> String {
+        format_idle(Duration::from_secs(seconds))
+    }
+
+    #[test]
+    fn idle_time_uses_the_largest_two_units() {
+        assert_eq!(idle(42), "42s");
+        assert_eq!(idle(5 * 60 + 3), "5m 3s");
+        assert_eq!(idle(2 * 86400 + 4 * 3600 + 59), "2d 4h");
+    }
+
+    #[test]
+    fn idle_time_leaves_out_zero_units() {
+        assert_eq!(idle(0), "0s");
+        assert_eq!(idle(300), "5m");
+        assert_eq!(idle(3600 + 1), "1h");
+        assert_eq!(idle(86400 + 3 * 60), "1d");
+    }
+
+    #[test]
+    fn idle_time_ignores_fractions_of_seconds() {
+        assert_eq!(format_idle(Duration::from_millis(1999)), "1s");
+    }
+}
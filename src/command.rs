@@ -0,0 +1,337 @@
+use crate::client::{ChatError, ChatResult};
+use crate::is_channel_name;
+
+/// What an argument of a command accepts
+#[derive(Clone, Copy, PartialEq)]
+pub enum ArgType {
+    /// One word, quotes keep whitespace in it and a backslash escapes the next character
+    Word,
+    /// A channel name, see `is_channel_name`
+    Channel,
+    /// The rest of the input as typed, only valid as the last argument
+    Rest
+}
+
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: ArgType
+}
+
+/// Arguments and help of a command, used to parse and check its parameters
+pub struct CommandSpec {
+    /// Name including the leading slash
+    pub name: &'static str,
+    pub args: &'static [Arg],
+    /// Arguments that must be given, the rest are optional. At most `args.len()` can be given.
+    pub min_args: usize,
+    pub help: &'static str
+}
+
+/// A word of the input and the byte index it starts at
+pub struct Token {
+    pub text: String,
+    pub start: usize
+}
+
+/// Splits input into words at whitespace, handling quotes and escapes
+pub struct Tokenizer<'a> {
+    input: &'a str,
+    position: usize
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self { input, position: 0 }
+    }
+
+    /// Everything after the words read so far with leading whitespace left out, None at the end
+    pub fn rest(&mut self) -> Option<&'a str> {
+        let rest = self.input[self.position..].trim_start();
+        self.position = self.input.len();
+        if rest.is_empty() { None } else { Some(rest) }
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Result<Token, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.input[self.position..];
+        let start = self.position + (rest.len() - rest.trim_start().len());
+        let mut chars = self.input[start..].char_indices().peekable();
+        chars.peek()?;
+        let mut text = String::new();
+        let mut quote = None;
+        let mut end = self.input.len();
+        while let Some((index, c)) = chars.next() {
+            match (c, quote) {
+                ('\\', _) => match chars.next() {
+                    Some((_, escaped)) => text.push(escaped),
+                    None => {
+                        self.position = self.input.len();
+                        return Some(Err("nothing to escape after the last \\".to_owned()));
+                    }
+                },
+                (c, Some(q)) if c == q => quote = None,
+                (c, Some(_)) => text.push(c),
+                ('"', None) | ('\'', None) => quote = Some(c),
+                (c, None) if c.is_whitespace() => {
+                    end = start + index;
+                    break;
+                }
+                (c, None) => text.push(c)
+            }
+        }
+        self.position = end;
+        match quote {
+            Some(q) => Some(Err(format!("missing closing {}", q))),
+            None => Some(Ok(Token { text, start }))
+        }
+    }
+}
+
+impl CommandSpec {
+    /// How to call the command, e.g. "/msg <target> <text...>" with optional arguments in brackets
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.to_owned();
+        for (index, arg) in self.args.iter().enumerate() {
+            let name = match arg.kind {
+                ArgType::Rest => format!("{}...", arg.name),
+                _ => arg.name.to_owned()
+            };
+            if index < self.min_args {
+                usage.push_str(&format!(" <{}>", name));
+            } else {
+                usage.push_str(&format!(" [{}]", name));
+            }
+        }
+        usage
+    }
+
+    /// Error about the parameters given to the command
+    pub fn invalid(&self, reason: impl ToString) -> ChatError {
+        ChatError::InvalidParameters { reason: reason.to_string(), usage: self.usage() }
+    }
+
+    /// Parse the input after the command name into parameters, a `Rest` argument takes the rest
+    /// of the input as typed
+    pub fn parse(&self, input: &str) -> ChatResult<Vec<String>> {
+        let mut tokens = Tokenizer::new(input);
+        let mut params = Vec::new();
+        for arg in self.args.iter() {
+            let param = match arg.kind {
+                ArgType::Rest => tokens.rest().map(str::to_owned),
+                _ => tokens.next().transpose().map_err(|reason| self.invalid(reason))?.map(|token| token.text)
+            };
+            match param {
+                Some(param) => params.push(param),
+                None => break
+            }
+        }
+        if let Some(token) = tokens.next() {
+            let token = token.map_err(|reason| self.invalid(reason))?;
+            return Err(self.invalid(format!("unexpected argument {}", token.text)));
+        }
+        self.check(&params)?;
+        Ok(params)
+    }
+
+    /// Check the number and types of `params`
+    pub fn check(&self, params: &[String]) -> ChatResult<()> {
+        if let Some(arg) = self.args.get(params.len()).filter(|_| params.len() < self.min_args) {
+            return Err(self.invalid(format!("missing {}", arg.name)));
+        }
+        if let Some(param) = params.get(self.args.len()) {
+            return Err(self.invalid(format!("unexpected argument {}", param)));
+        }
+        for (arg, param) in self.args.iter().zip(params) {
+            match arg.kind {
+                _ if param.is_empty() => return Err(self.invalid(format!("{} can't be empty", arg.name))),
+                ArgType::Channel if !is_channel_name(param) => return Err(self.invalid(format!("invalid channel name {}", param))),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split input starting with a slash into the command name and the input after it
+pub fn split_command(input: &str) -> Option<(&str, &str)> {
+    if !input.starts_with('/') {
+        return None;
+    }
+    match input.find(char::is_whitespace) {
+        Some(index) => Some((&input[..index], &input[index..])),
+        None => Some((input, ""))
+    }
+}
+
+/// Input lines an alias expands to: the commands of `expansion` separated by `;`, with `$1`..`$9`
+/// replaced by the words of `args` and `$*` by all of them. The words are quoted again where needed
+/// so each stays one word when the lines are parsed. `\;` and `$$` stand for a plain `;` and `$`.
+pub fn expand_alias(expansion: &str, args: &str) -> Result<Vec<String>, String> {
+    let words: Vec<String> = Tokenizer::new(args).map(|token| token.map(|token| token.text)).collect::<Result<_, _>>()?;
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut chars = expansion.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek().copied()) {
+            ('\\', Some(';')) | ('$', Some('$')) => {
+                chars.next();
+                line.push(if c == '$' { '$' } else { ';' });
+            },
+            (';', _) => lines.push(std::mem::take(&mut line)),
+            ('$', Some('*')) => {
+                chars.next();
+                let quoted: Vec<String> = words.iter().map(|word| quote(word)).collect();
+                line.push_str(&quoted.join(" "));
+            },
+            ('$', Some(digit @ '1'..='9')) => {
+                chars.next();
+                let index = digit as usize - '1' as usize;
+                let word = words.get(index).ok_or_else(|| format!("missing argument ${}", digit))?;
+                line.push_str(&quote(word));
+            },
+            (c, _) => line.push(c)
+        }
+    }
+    lines.push(line);
+    Ok(lines.iter().map(|line| line.trim()).filter(|line| !line.is_empty()).map(str::to_owned).collect())
+}
+
+/// `word` as `Tokenizer` reads it back as one word, in double quotes if it is empty or contains
+/// whitespace, quotes or backslashes
+fn quote(word: &str) -> String {
+    if !word.is_empty() && !word.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '\\') {
+        return word.to_owned();
+    }
+    let mut quoted = String::from("\"");
+    for c in word.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MSG: CommandSpec = CommandSpec {
+        name: "/msg",
+        args: &[Arg { name: "target", kind: ArgType::Word }, Arg { name: "text", kind: ArgType::Rest }],
+        min_args: 2,
+        help: ""
+    };
+    const PART: CommandSpec = CommandSpec {
+        name: "/part",
+        args: &[Arg { name: "channel", kind: ArgType::Channel }],
+        min_args: 0,
+        help: ""
+    };
+
+    fn words(input: &str) -> Result<Vec<String>, String> {
+        Tokenizer::new(input).map(|token| token.map(|token| token.text)).collect()
+    }
+
+    fn reason(result: ChatResult<Vec<String>>) -> String {
+        match result {
+            Err(ChatError::InvalidParameters { reason, .. }) => reason,
+            _ => panic!("expected invalid parameters")
+        }
+    }
+
+    #[test]
+    fn splits_at_whitespace() {
+        assert_eq!(words("  a  b\tc ").unwrap(), ["a", "b", "c"]);
+        assert!(words("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn quotes_keep_whitespace() {
+        assert_eq!(words(r#""Big Ferris" 'it''s' x"y z""#).unwrap(), ["Big Ferris", "its", "xy z"]);
+        assert_eq!(words(r#"'say "hi"'"#).unwrap(), [r#"say "hi""#]);
+        assert_eq!(words(r#""""#).unwrap(), [""]);
+    }
+
+    #[test]
+    fn backslash_escapes_the_next_character() {
+        assert_eq!(words(r#"a\ b \"c\" d\\"#).unwrap(), ["a b", "\"c\"", "d\\"]);
+        assert_eq!(words(r#""a\"b""#).unwrap(), ["a\"b"]);
+        assert!(words("a\\").is_err());
+    }
+
+    #[test]
+    fn unterminated_quotes_are_errors() {
+        assert_eq!(words(r#"a "b c"#).unwrap_err(), "missing closing \"");
+        assert_eq!(words("'b").unwrap_err(), "missing closing '");
+    }
+
+    #[test]
+    fn rest_argument_is_taken_as_typed() {
+        assert_eq!(MSG.parse(r#" ferris  hi  "there" \o/"#).unwrap(), ["ferris", r#"hi  "there" \o/"#]);
+        assert_eq!(MSG.parse(r##" "#ops" hi"##).unwrap(), ["#ops", "hi"]);
+    }
+
+    #[test]
+    fn too_few_arguments() {
+        assert_eq!(reason(MSG.parse("")), "missing target");
+        assert_eq!(reason(MSG.parse("ferris")), "missing text");
+    }
+
+    #[test]
+    fn too_many_arguments() {
+        assert_eq!(reason(PART.parse("#a #b")), "unexpected argument #b");
+        assert_eq!(reason(PART.parse(r#"#a "b"#)), "missing closing \"");
+    }
+
+    #[test]
+    fn arguments_are_checked() {
+        assert_eq!(PART.parse("").unwrap(), Vec::<String>::new());
+        assert_eq!(reason(PART.parse("ops")), "invalid channel name ops");
+        assert_eq!(reason(PART.parse(r#""""#)), "channel can't be empty");
+    }
+
+    #[test]
+    fn usage_marks_optional_and_rest_arguments() {
+        assert_eq!(MSG.usage(), "/msg <target> <text...>");
+        assert_eq!(PART.usage(), "/part [channel]");
+    }
+
+    #[test]
+    fn splits_commands() {
+        assert_eq!(split_command("/msg a b"), Some(("/msg", " a b")));
+        assert_eq!(split_command("/quit"), Some(("/quit", "")));
+        assert_eq!(split_command("hello"), None);
+    }
+
+    #[test]
+    fn alias_arguments_are_substituted() {
+        let lines = expand_alias("/join #ops; /msg deploybot status $*", "of api").unwrap();
+        assert_eq!(lines, ["/join #ops", "/msg deploybot status of api"]);
+        assert_eq!(expand_alias("/msg $2 $1", "hi ferris").unwrap(), ["/msg ferris hi"]);
+        assert_eq!(expand_alias(r"/me costs $$5\; cheap", "").unwrap(), ["/me costs $5; cheap"]);
+        assert_eq!(expand_alias("/msg $1 $2", "ferris").unwrap_err(), "missing argument $2");
+    }
+
+    #[test]
+    fn quoted_alias_argument_stays_one_word() {
+        let lines = expand_alias("/nick $1", r#""Big Ferris" c"#).unwrap();
+        assert_eq!(lines, [r#"/nick "Big Ferris""#]);
+        let (_, args) = split_command(&lines[0]).unwrap();
+        assert_eq!(words(args).unwrap(), ["Big Ferris"]);
+
+        let lines = expand_alias("/msg $*", r#"'a "b"' c\\d "" ;"#).unwrap();
+        let (_, args) = split_command(&lines[0]).unwrap();
+        assert_eq!(words(args).unwrap(), [r#"a "b""#, r"c\d", "", ";"]);
+    }
+
+    #[test]
+    fn alias_arguments_cannot_add_commands() {
+        assert_eq!(expand_alias("/msg ferris $1", r#""hi; /quit""#).unwrap(), [r#"/msg ferris "hi; /quit""#]);
+        assert_eq!(expand_alias("/msg ferris $*", "hi; /quit").unwrap(), ["/msg ferris hi; /quit"]);
+    }
+}
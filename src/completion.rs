@@ -0,0 +1,152 @@
+/// Completes the word before the cursor, cycling through the candidates on repeated calls
+#[derive(Default)]
+pub struct Completer {
+    /// Most recently active first
+    nicks: Vec<String>,
+    channels: Vec<String>,
+    /// Command names including the leading slash
+    commands: Vec<String>,
+    cycle: Option<Cycle>
+}
+
+struct Cycle {
+    /// Input before the first completion
+    original: String,
+    start: usize,
+    end: usize,
+    candidates: Vec<String>,
+    index: usize,
+    /// Input and cursor after the latest completion, to tell if the next call continues the cycle
+    completed: (String, usize)
+}
+
+impl Completer {
+    /// Offer `nick` first when completing nicks
+    pub fn add_nick(&mut self, nick: &str) {
+        self.remove_nick(nick);
+        self.nicks.insert(0, nick.to_owned());
+    }
+    pub fn remove_nick(&mut self, nick: &str) {
+        self.nicks.retain(|n| n != nick);
+    }
+    pub fn add_channel(&mut self, channel: &str) {
+        if !self.channels.iter().any(|c| c == channel) {
+            self.channels.push(channel.to_owned());
+        }
+    }
+    pub fn remove_channel(&mut self, channel: &str) {
+        self.channels.retain(|c| c != channel);
+    }
+    /// Complete these command names at the start of the input, e.g. `CommandRegistry::names`
+    pub fn set_commands(&mut self, commands: Vec<String>) {
+        self.commands = commands;
+    }
+
+    /// Complete the word ending at byte index `cursor` of `input`, returns the new input and cursor
+    pub fn complete(&mut self, input: &str, cursor: usize) -> Option<(String, usize)> {
+        match self.cycle {
+            Some(ref mut cycle) if cycle.completed.0 == input && cycle.completed.1 == cursor => {
+                cycle.index = (cycle.index + 1) % cycle.candidates.len();
+            }
+            _ => self.cycle = self.start(input, cursor)
+        }
+        let cycle = self.cycle.as_mut()?;
+        let mut completed = cycle.original[..cycle.start].to_owned();
+        completed.push_str(&cycle.candidates[cycle.index]);
+        let cursor = completed.len();
+        completed.push_str(&cycle.original[cycle.end..]);
+        cycle.completed = (completed.clone(), cursor);
+        Some((completed, cursor))
+    }
+
+    fn start(&self, input: &str, cursor: usize) -> Option<Cycle> {
+        let start = input[..cursor].char_indices().rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map(|(i, c)| i + c.len_utf8())
+            .unwrap_or(0);
+        let word = &input[start..cursor];
+        if word.is_empty() {
+            return None;
+        }
+        let (names, suffix): (Vec<&str>, &str) = if start == 0 && word.starts_with('/') {
+            (self.commands.iter().map(String::as_str).collect(), " ")
+        } else if word.starts_with('#') {
+            (self.channels.iter().map(String::as_str).collect(), " ")
+        } else if start == 0 {
+            // Addressing someone at the start of a message
+            (self.nicks.iter().map(String::as_str).collect(), ": ")
+        } else {
+            (self.nicks.iter().map(String::as_str).collect(), " ")
+        };
+        // Don't double the space if there already is one after the cursor
+        let suffix = if input[cursor..].starts_with(char::is_whitespace) { suffix.trim_end() } else { suffix };
+        let word = word.to_lowercase();
+        let candidates: Vec<String> = names.into_iter()
+            .filter(|name| name.to_lowercase().starts_with(&word))
+            .map(|name| format!("{}{}", name, suffix))
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(Cycle { original: input.to_owned(), start, end: cursor, candidates, index: 0, completed: (String::new(), 0) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn completer() -> Completer {
+        let mut completer = Completer::default();
+        completer.add_nick("alice");
+        completer.add_nick("Bob");
+        completer.add_nick("albert");
+        completer.add_channel("#rust");
+        completer.add_channel("#ops");
+        completer.set_commands(vec!["/join".to_owned(), "/msg".to_owned()]);
+        completer
+    }
+
+    fn complete(completer: &mut Completer, input: &str) -> Option<(String, usize)> {
+        completer.complete(input, input.len())
+    }
+
+    #[test]
+    fn completes_nicks_most_recent_first() {
+        let mut completer = completer();
+        assert_eq!(complete(&mut completer, "al"), Some(("albert: ".to_owned(), 8)));
+        assert_eq!(complete(&mut completer, "hi b"), Some(("hi Bob ".to_owned(), 7)));
+        completer.add_nick("alice");
+        assert_eq!(complete(&mut completer, "hi al"), Some(("hi alice ".to_owned(), 9)));
+    }
+
+    #[test]
+    fn cycles_through_candidates() {
+        let mut completer = completer();
+        let (input, cursor) = complete(&mut completer, "al").unwrap();
+        let (input, cursor) = completer.complete(&input, cursor).unwrap();
+        assert_eq!(input, "alice: ");
+        let (input, _) = completer.complete(&input, cursor).unwrap();
+        assert_eq!(input, "albert: ");
+    }
+
+    #[test]
+    fn completes_commands_and_channels() {
+        let mut completer = completer();
+        assert_eq!(complete(&mut completer, "/j"), Some(("/join ".to_owned(), 6)));
+        assert_eq!(complete(&mut completer, "/join #o"), Some(("/join #ops ".to_owned(), 11)));
+        // Commands only at the start of the input
+        assert_eq!(complete(&mut completer, "see /j"), None);
+        completer.remove_channel("#ops");
+        assert_eq!(complete(&mut completer, "/join #o"), None);
+    }
+
+    #[test]
+    fn completes_before_the_cursor() {
+        let mut completer = completer();
+        assert_eq!(completer.complete("hi bo there", 5), Some(("hi Bob there".to_owned(), 6)));
+        assert_eq!(completer.complete("hi  there", 3), None);
+        completer.remove_nick("Bob");
+        assert_eq!(completer.complete("hi bo there", 5), None);
+    }
+}
@@ -0,0 +1,63 @@
+/// Stable index below `colors` into a palette of that many nick colors, the same in every client
+/// and session. Panics if `colors` is zero.
+pub fn nick_color(nick: &str, colors: usize) -> usize {
+    assert!(colors > 0, "nick_color needs at least one color");
+    // FNV-1a, the std hashers are randomly seeded
+    let hash = nick.bytes().fold(0xcbf29ce484222325u64, |hash, b| (hash ^ b as u64).wrapping_mul(0x100000001b3));
+    (hash % colors as u64) as usize
+}
+
+/// Whether `content` mentions `nick` or contains any of `words`, matched as whole words ignoring case
+pub fn is_highlight(content: &str, nick: &str, words: &[String]) -> bool {
+    let content = content.to_lowercase();
+    std::iter::once(nick).chain(words.iter().map(String::as_str))
+        .any(|word| contains_word(&content, &word.to_lowercase()))
+}
+
+fn contains_word(content: &str, word: &str) -> bool {
+    if word.is_empty() {
+        return false;
+    }
+    content.match_indices(word).any(|(start, _)| {
+        let before = content[..start].chars().next_back();
+        let after = content[start + word.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nick_color_is_stable_and_uses_the_whole_palette() {
+        assert_eq!(nick_color("ferris", 12), nick_color("ferris", 12));
+        let mut used = [false; 12];
+        for n in 0..200 {
+            used[nick_color(&format!("nick{}", n), 12)] = true;
+        }
+        assert!(used.iter().all(|&used| used));
+    }
+
+    #[test]
+    #[should_panic]
+    fn nick_color_needs_a_color() {
+        nick_color("ferris", 0);
+    }
+
+    #[test]
+    fn mentions_match_whole_words_ignoring_case() {
+        assert!(is_highlight("hey Ferris, look", "ferris", &[]));
+        assert!(is_highlight("ferris: hi", "ferris", &[]));
+        assert!(!is_highlight("ferriswheel", "ferris", &[]));
+        assert!(!is_highlight("superferris", "ferris", &[]));
+    }
+
+    #[test]
+    fn highlight_words_match_like_the_nick() {
+        let words = vec!["deploy".to_owned(), String::new()];
+        assert!(is_highlight("Deploy finished", "ferris", &words));
+        assert!(!is_highlight("deployed", "ferris", &words));
+        assert!(!is_highlight("nothing here", "ferris", &words));
+    }
+}
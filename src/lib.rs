@@ -1,17 +1,107 @@
 use serde::{Serialize, Deserialize};
-use bincode;
+use std::time::{Duration, SystemTime};
 
+pub mod admin;
 pub mod client;
+pub mod command;
+pub mod completion;
+pub mod highlight;
+#[cfg(feature = "logging")]
+pub mod logging;
+
+/// Nick of clients that have not set one
+pub const DEFAULT_NICK: &str = "anonymous";
+
+/// How a chat message is meant to be shown
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKind {
+    Normal,
+    /// Something the sender does, sent with `/me` and shown as "* nick waves"
+    Action,
+    /// Informational text, e.g. from a bot, not meant to be replied to automatically
+    Notice,
+    /// Sent by the server itself, clients can't send these
+    System
+}
 
 #[derive(Serialize, Deserialize)]
 pub enum ServerMessage {
-    Message { nick: String, content: String }
+    Message { nick: String, kind: MessageKind, content: String },
+    Notice { content: String },
+    ServerShutdown { reason: String, reconnect_after: Option<Duration> },
+    ChannelMessage { channel: String, nick: String, kind: MessageKind, content: String },
+    /// Sent to the recipient and echoed back to the sender
+    PrivateMessage { from: String, to: String, kind: MessageKind, content: String },
+    Joined { channel: String, nick: String },
+    Parted { channel: String, nick: String },
+    /// Everyone on a channel, sent to its members whenever someone joins, leaves or changes nick
+    Members { channel: String, members: Vec<Member> },
+    /// Topic of a channel, sent to a client joining it and to its members when it changes. None when
+    /// the topic is removed.
+    Topic { channel: String, topic: Option<Topic> },
+    /// Reply to `ListUsers`, sorted by nick
+    UserList { channel: Option<String>, users: Vec<UserInfo> },
+    /// Reply to `ListChannels`, sorted by name
+    ChannelList { channels: Vec<ChannelInfo> },
+    Pong { token: u64 }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Member {
+    pub nick: String,
+    pub operator: bool,
+    pub away: bool
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Topic {
+    pub text: String,
+    /// Nick of whoever set the topic
+    pub set_by: String,
+    pub set_at: SystemTime
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UserInfo {
+    pub nick: String,
+    pub operator: bool,
+    /// Time since the user last sent a message
+    pub idle: Duration,
+    /// Reason given when going away, None when not away
+    pub away: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ChannelInfo {
+    pub name: String,
+    pub members: usize,
+    pub topic: Option<Topic>
 }
 
 #[derive(Serialize, Deserialize)]
 pub enum ClientMessage {
-    Message { content: String },
-    Nick { nick: String }
+    Message { kind: MessageKind, content: String },
+    Nick { nick: String },
+    Join { channel: String },
+    Part { channel: String },
+    ChannelMessage { channel: String, kind: MessageKind, content: String },
+    PrivateMessage { nick: String, kind: MessageKind, content: String },
+    /// Set the topic of a channel the client is on, an empty topic removes it
+    SetTopic { channel: String, topic: String },
+    /// Ask for the users on a channel or, without one, on the server, answered with `UserList`
+    ListUsers { channel: Option<String> },
+    /// Ask for the channels, answered with `ChannelList`
+    ListChannels,
+    /// Mark the client away with a reason told to those messaging it privately, None when back
+    Away { reason: Option<String> },
+    /// Answered with a `Pong` carrying the same token, for measuring latency
+    Ping { token: u64 }
+}
+
+/// Channel names start with '#' and contain no whitespace
+pub fn is_channel_name(name: &str) -> bool {
+    name.len() > 1 && name.starts_with('#') && !name.contains(char::is_whitespace)
 }
 
 impl ServerMessage {
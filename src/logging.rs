@@ -0,0 +1,69 @@
+use env_logger::{fmt::Formatter, Builder, Env};
+use log::kv::{self, Key, Value, VisitSource};
+use serde_json::{Map, Value as JsonValue};
+use std::io::{self, Write};
+use std::str::FromStr;
+use thiserror::Error;
+
+pub use env_logger::Target;
+
+pub const LOG_FORMAT_ENV: &str = "CHATRS_LOG_FORMAT";
+
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Error)]
+#[error("Unknown log format: {0}")]
+pub struct UnknownLogFormat(String);
+
+impl LogFormat {
+    pub fn from_env() -> Result<Self, UnknownLogFormat> {
+        match std::env::var(LOG_FORMAT_ENV) {
+            Ok(format) => format.parse(),
+            Err(_) => Ok(Self::Text)
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = UnknownLogFormat;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(UnknownLogFormat(s.to_owned()))
+        }
+    }
+}
+
+/// Logger builder filtered by `RUST_LOG`, falling back to `default_filter`
+pub fn builder(default_filter: &str, format: LogFormat) -> Builder {
+    let mut builder = Builder::from_env(Env::default().default_filter_or(default_filter));
+    if let LogFormat::Json = format {
+        builder.format(format_json);
+    }
+    builder
+}
+
+fn format_json(buf: &mut Formatter, record: &log::Record) -> io::Result<()> {
+    let mut fields = Map::new();
+    fields.insert("timestamp".to_owned(), buf.timestamp().to_string().into());
+    fields.insert("level".to_owned(), record.level().as_str().into());
+    fields.insert("target".to_owned(), record.target().into());
+    fields.insert("message".to_owned(), record.args().to_string().into());
+    record.key_values().visit(&mut JsonFields(&mut fields))
+        .map_err(io::Error::other)?;
+    writeln!(buf, "{}", JsonValue::Object(fields))
+}
+
+struct JsonFields<'a>(&'a mut Map<String, JsonValue>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = serde_json::to_value(&value).map_err(kv::Error::boxed)?;
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
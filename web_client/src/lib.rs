@@ -1,23 +1,103 @@
 #![recursion_limit = "1024"]
 
-use chatrs::client::{ChatClient, ChatClientCommon, ChatError, ChatResult, ChatUserInterface};
+use chatrs::client::{
+    format_idle, ChatClient, ChatClientCommon, ChatError, ChatResult, ChatUserInterface,
+    CommandRegistry, Target,
+};
+use chatrs::command::{Arg, ArgType, CommandSpec};
+use chatrs::completion::Completer;
+use chatrs::highlight::{is_highlight, nick_color};
+use chatrs::{ChannelInfo, Member, MessageKind, Topic, UserInfo, DEFAULT_NICK};
+use std::collections::{BTreeMap, HashSet};
+use std::time::{Duration, UNIX_EPOCH};
 use wasm_bindgen::prelude::*;
+use web_sys::{Notification, NotificationOptions, NotificationPermission};
+use yew::format::Text;
 use yew::prelude::*;
+use yew::services::storage::{Area, StorageService};
+use yew::services::timeout::{TimeoutService, TimeoutTask};
 use yew::services::websocket::{WebSocketService, WebSocketStatus, WebSocketTask};
 
+/// Commands of the web UI, registered with the common ones
+const UI_COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "/mute",
+        args: &[Arg {
+            name: "conversation",
+            kind: ArgType::Word,
+        }],
+        min_args: 1,
+        help: "Stop notifications from a channel or nick",
+    },
+    CommandSpec {
+        name: "/unmute",
+        args: &[Arg {
+            name: "conversation",
+            kind: ArgType::Word,
+        }],
+        min_args: 1,
+        help: "Notify about a muted channel or nick again",
+    },
+];
+
+/// localStorage key of the comma separated words highlighting messages like mentions of the user's nick
+const HIGHLIGHT_WORDS_KEY: &str = "chatrs.highlight_words";
+/// localStorage key of the aliases, one "name expansion" per line
+const ALIASES_KEY: &str = "chatrs.aliases";
+
 enum Message {
-    Chat { nick: String, content: String },
-    ChangeNick { nick: String },
-    Status { content: String },
-    Error { content: String },
+    Chat {
+        nick: String,
+        kind: MessageKind,
+        content: String,
+        highlight: bool,
+    },
+    /// Channel or private message, the web client shows all conversations in one list
+    Conversation {
+        target: String,
+        nick: String,
+        kind: MessageKind,
+        content: String,
+        highlight: bool,
+    },
+    ChangeNick {
+        nick: String,
+    },
+    Notice {
+        content: String,
+    },
+    Status {
+        content: String,
+    },
+    Error {
+        content: String,
+    },
+    /// Reply to /who
+    Users {
+        channel: Option<String>,
+        users: Vec<UserInfo>,
+    },
+    /// Reply to /list
+    Channels {
+        channels: Vec<ChannelInfo>,
+    },
 }
 
 struct Model {
     link: ComponentLink<Self>,
     nick: String,
     input: Option<String>,
+    completer: Completer,
+    commands: CommandRegistry,
+    highlight_words: Vec<String>,
+    /// Conversations that never notify
+    muted: HashSet<String>,
     messages: Vec<Message>,
+    /// Topics of the joined channels, shown in the header
+    topics: BTreeMap<String, Topic>,
+    server: Option<String>,
     ws: Option<WebSocketTask>,
+    reconnect: Option<TimeoutTask>,
 }
 
 enum Msg {
@@ -28,6 +108,7 @@ enum Msg {
     MessageInput(String),
     RecvMessage(Vec<u8>),
     Enter,
+    Complete,
     Nope,
 }
 
@@ -35,28 +116,67 @@ impl Component for Model {
     type Message = Msg;
     type Properties = ();
     fn create(_: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut commands = CommandRegistry::default();
+        commands.register(UI_COMMANDS);
+        for (name, expansion) in load_aliases() {
+            // Aliases clashing with commands can't be used, drop them
+            commands.set_alias(&name, &expansion).ok();
+        }
+        let mut completer = Completer::default();
+        completer.set_commands(commands.names());
         Self {
             link,
-            nick: "anonymous".to_owned(),
+            nick: DEFAULT_NICK.to_owned(),
             input: None,
+            completer,
+            commands,
+            highlight_words: load_highlight_words(),
+            muted: HashSet::new(),
             messages: Vec::new(),
+            topics: BTreeMap::new(),
+            server: None,
             ws: None,
+            reconnect: None,
         }
     }
 
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
         match msg {
-            Msg::Connect(address) => self.connect(address),
-            Msg::Disconnect => Ok(self.disconnect()),
-            Msg::Connected(address) => Ok(self.connected(address)),
-            Msg::Disconnected => Ok(self.disconnected()),
-            Msg::MessageInput(input) => Ok(self.input = Some(input)),
+            Msg::Connect(address) => {
+                request_notification_permission();
+                self.connect(address)
+            }
+            Msg::Disconnect => {
+                self.disconnect();
+                Ok(())
+            }
+            Msg::Connected(address) => {
+                self.connected(address);
+                Ok(())
+            }
+            Msg::Disconnected => {
+                self.ws = None;
+                self.disconnected();
+                Ok(())
+            }
+            Msg::MessageInput(input) => {
+                self.input = Some(input);
+                Ok(())
+            }
             Msg::RecvMessage(data) => self.recv_binary(&data),
             Msg::Enter => self
                 .input
                 .take()
                 .map(|input| self.handle_input(input))
                 .unwrap_or(Ok(())),
+            Msg::Complete => {
+                // The input element's cursor is not tracked, complete at the end
+                let input = self.input.clone().unwrap_or_default();
+                if let Some((input, _)) = self.completer.complete(&input, input.len()) {
+                    self.input = Some(input);
+                }
+                Ok(())
+            }
             Msg::Nope => Ok(()),
         }
         .unwrap_or_else(|e| self.handle_error(e));
@@ -76,14 +196,26 @@ impl Component for Model {
                     <button onclick=self.link.callback(|_| Msg::Disconnect)
                             disabled=!self.is_connected()>{ "Disconnect" }</button>
                 </div>
+                <ul class="topics">
+                    {for self.topics.iter().map(|(channel, topic)| view_topic(channel, topic)) }
+                </ul>
                 <ul class="buffer">
-                    {for self.messages.iter().map(|m| view_message(m)) }
+                    {for self.messages.iter().map(|m| view_message(m, &self.nick)) }
                 </ul>
                 <div class="inputbar">
                     <label for="input">{ &self.nick }</label>
                     <input value={ if let Some(ref m) = self.input { m.as_str() } else { "" } }
                            name="input"
                            onkeypress=self.link.callback(|e: KeyboardEvent| { if e.key() == "Enter" { Msg::Enter } else { Msg::Nope } })
+                           onkeydown=self.link.callback(|e: KeyboardEvent| {
+                               if e.key() == "Tab" {
+                                   // Keep the focus in the input
+                                   e.prevent_default();
+                                   Msg::Complete
+                               } else {
+                                   Msg::Nope
+                               }
+                           })
                            oninput=self.link.callback(|e: InputData| Msg::MessageInput(e.value))
                     />
                 </div>
@@ -92,20 +224,201 @@ impl Component for Model {
     }
 }
 
-fn view_message(m: &Message) -> Html {
+fn load_aliases() -> Vec<(String, String)> {
+    let aliases: Text = match StorageService::new(Area::Local) {
+        Ok(storage) => storage.restore(ALIASES_KEY),
+        Err(_) => return Vec::new(),
+    };
+    aliases
+        .map(|aliases| {
+            aliases
+                .lines()
+                .filter_map(|line| line.split_once(' '))
+                .map(|(name, expansion)| (name.to_owned(), expansion.to_owned()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn save_aliases(aliases: &BTreeMap<String, String>) {
+    if let Ok(mut storage) = StorageService::new(Area::Local) {
+        let lines: Vec<String> = aliases
+            .iter()
+            .map(|(name, expansion)| format!("{} {}", name, expansion))
+            .collect();
+        storage.store(ALIASES_KEY, Ok(lines.join("\n")));
+    }
+}
+
+fn load_highlight_words() -> Vec<String> {
+    let words: Text = match StorageService::new(Area::Local) {
+        Ok(storage) => storage.restore(HIGHLIGHT_WORDS_KEY),
+        Err(_) => return Vec::new(),
+    };
+    words
+        .map(|words| {
+            words
+                .split(',')
+                .map(str::trim)
+                .filter(|word| !word.is_empty())
+                .map(str::to_owned)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Ask for permission to show notifications unless the user already decided
+fn request_notification_permission() {
+    if Notification::permission() == NotificationPermission::Default {
+        // The answer is checked when notifying
+        let _ = Notification::request_permission();
+    }
+}
+
+/// Number of `nick-N` colors in chatrs.css
+const NICK_COLORS: usize = 8;
+
+/// Class of a nick, other users get one of the `nick-N` colors
+fn nick_class(nick: &str, own_nick: &str) -> String {
+    if nick == own_nick {
+        "nick".to_owned()
+    } else {
+        format!("nick nick-{}", nick_color(nick, NICK_COLORS))
+    }
+}
+
+/// A channel's topic in the header, with who set it and when as its tooltip
+fn view_topic(channel: &str, topic: &Topic) -> Html {
+    let title = format!("Set by {} on {}", topic.set_by, format_time(topic));
+    html! {
+        <li title=title><span class="target">{ channel }{ ": " }</span>{ &topic.text }</li>
+    }
+}
+
+/// Status message about the topic of `channel`, with who set it and when
+fn describe_topic(channel: &str, topic: Option<&Topic>) -> String {
+    match topic {
+        Some(topic) => format!(
+            "Topic of {}: {} (set by {} on {})",
+            channel,
+            topic.text,
+            topic.set_by,
+            format_time(topic)
+        ),
+        None => format!("No topic on {}", channel),
+    }
+}
+
+/// When the topic was set in the browser's locale and time zone
+fn format_time(topic: &Topic) -> String {
+    let millis = topic
+        .set_at
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |since| since.as_millis() as f64);
+    js_sys::Date::new(&JsValue::from_f64(millis))
+        .to_locale_string("default", &JsValue::UNDEFINED)
+        .into()
+}
+
+/// A chat message with the nick shown as "nick: ", "* nick " or "-nick- " depending on its kind
+fn view_chat(
+    target: Option<&str>,
+    nick: &str,
+    kind: MessageKind,
+    content: &str,
+    highlight: bool,
+    own_nick: &str,
+) -> Html {
+    let mut classes = vec![match kind {
+        MessageKind::Normal => "chat",
+        MessageKind::Action => "action",
+        MessageKind::Notice => "notice",
+        MessageKind::System => "status",
+    }];
+    if highlight {
+        classes.push("mention");
+    }
+    let (before, after) = match kind {
+        MessageKind::Normal | MessageKind::System => ("", ": "),
+        MessageKind::Action => ("* ", " "),
+        MessageKind::Notice => ("-", "- "),
+    };
+    let target = match target {
+        Some(target) => html! { <span class="target">{ "[" }{ target }{ "] " }</span> },
+        None => html! {},
+    };
+    html! {
+        <li class=classes>{ target }{ before }<span class=nick_class(nick, own_nick)>{ nick }</span>{ after }{ content }</li>
+    }
+}
+
+fn view_message(m: &Message, own_nick: &str) -> Html {
     match m {
-        Message::Chat { nick, content } => html! {
-            <li><span class="nick">{ nick }{ ": " }</span> { content }</li>
-        },
+        Message::Chat {
+            nick,
+            kind,
+            content,
+            highlight,
+        } => view_chat(None, nick, *kind, content, *highlight, own_nick),
+        Message::Conversation {
+            target,
+            nick,
+            kind,
+            content,
+            highlight,
+        } => view_chat(Some(target), nick, *kind, content, *highlight, own_nick),
         Message::ChangeNick { nick } => html! {
             <li class="status">{ "Changed nick to " }<span class="nick">{ nick }</span></li>
         },
+        Message::Notice { content } => html! {
+            <li class="notice">{ content }</li>
+        },
         Message::Status { content } => html! {
             <li class="status">{ content }</li>
         },
         Message::Error { content } => html! {
             <li class="error">{ content }</li>
         },
+        Message::Users { channel, users } => {
+            let title = match channel {
+                Some(channel) => format!("Users on {} ({})", channel, users.len()),
+                None => format!("Users on the server ({})", users.len()),
+            };
+            html! {
+                <li class="status">{ title }
+                    <ul class="list">{ for users.iter().map(|user| view_user(user, own_nick)) }</ul>
+                </li>
+            }
+        }
+        Message::Channels { channels } => html! {
+            <li class="status">{ format!("Channels ({})", channels.len()) }
+                <ul class="list">{ for channels.iter().map(view_channel) }</ul>
+            </li>
+        },
+    }
+}
+
+fn view_user(user: &UserInfo, own_nick: &str) -> Html {
+    let operator = if user.operator { "@" } else { "" };
+    let away = match user.away {
+        Some(ref reason) => format!(", away: {}", reason),
+        None => String::new(),
+    };
+    html! {
+        <li>
+            { operator }<span class=nick_class(&user.nick, own_nick)>{ &user.nick }</span>
+            { format!(" idle {}{}", format_idle(user.idle), away) }
+        </li>
+    }
+}
+
+fn view_channel(channel: &ChannelInfo) -> Html {
+    let topic = match channel.topic {
+        Some(ref topic) => format!(": {}", topic.text),
+        None => String::new(),
+    };
+    html! {
+        <li><span class="target">{ &channel.name }</span>{ format!(" ({} users){}", channel.members, topic) }</li>
     }
 }
 
@@ -114,6 +427,7 @@ impl Model {
         self.handle_status(format!("Connected to {}", address));
     }
     fn disconnected(&mut self) {
+        self.topics.clear();
         self.handle_status("Disconnected");
     }
     fn handle_status(&mut self, content: impl ToString) {
@@ -126,16 +440,193 @@ impl Model {
             content: content.to_string(),
         });
     }
+    /// Show a browser notification about a message in `target` while the page is hidden, unless muted
+    fn notify(&self, target: &str, title: &str, body: &str) {
+        if self.muted.contains(target)
+            || Notification::permission() != NotificationPermission::Granted
+        {
+            return;
+        }
+        let hidden = web_sys::window()
+            .and_then(|window| window.document())
+            .is_some_and(|document| document.hidden());
+        if hidden {
+            let mut options = NotificationOptions::new();
+            options.body(body);
+            // The browser closes the notification, there is nothing to keep
+            let _ = Notification::new_with_options(title, &options);
+        }
+    }
+    /// Whether a message from `nick` mentions the user or any of the highlight words
+    fn is_highlight(&self, nick: &str, content: &str) -> bool {
+        // The default nick is not worth highlighting
+        let own_nick = if self.nick == DEFAULT_NICK {
+            ""
+        } else {
+            &self.nick
+        };
+        nick != self.nick && is_highlight(content, own_nick, &self.highlight_words)
+    }
 }
 
 impl ChatUserInterface for Model {
-    fn receive_message(&mut self, nick: String, content: String) {
-        self.messages.push(Message::Chat { nick, content });
+    fn receive_message(&mut self, nick: String, kind: MessageKind, content: String) {
+        if nick != self.nick {
+            self.completer.add_nick(&nick);
+        }
+        let highlight = self.is_highlight(&nick, &content);
+        if highlight {
+            self.notify("server", &nick, &content);
+        }
+        self.messages.push(Message::Chat {
+            nick,
+            kind,
+            content,
+            highlight,
+        });
+    }
+    fn receive_notice(&mut self, content: String) {
+        self.messages.push(Message::Notice { content });
+    }
+    fn receive_channel_message(
+        &mut self,
+        channel: String,
+        nick: String,
+        kind: MessageKind,
+        content: String,
+    ) {
+        if nick != self.nick {
+            self.completer.add_nick(&nick);
+        }
+        let highlight = self.is_highlight(&nick, &content);
+        if highlight {
+            self.notify(&channel, &format!("{} in {}", nick, channel), &content);
+        }
+        self.messages.push(Message::Conversation {
+            target: channel,
+            nick,
+            kind,
+            content,
+            highlight,
+        });
+    }
+    fn receive_private_message(
+        &mut self,
+        from: String,
+        to: String,
+        kind: MessageKind,
+        content: String,
+    ) {
+        let target = if from == self.nick { to } else { from.clone() };
+        self.completer.add_nick(&target);
+        let highlight = self.is_highlight(&from, &content);
+        if from != self.nick {
+            self.notify(&target, &from, &content);
+        }
+        self.messages.push(Message::Conversation {
+            target,
+            nick: from,
+            kind,
+            content,
+            highlight,
+        });
+    }
+    fn user_joined(&mut self, channel: String, nick: String) {
+        self.handle_status(format!("{} joined {}", nick, channel));
+    }
+    fn user_parted(&mut self, channel: String, nick: String) {
+        self.handle_status(format!("{} left {}", nick, channel));
+    }
+    fn channel_members(&mut self, _channel: String, members: Vec<Member>) {
+        for member in members.iter() {
+            if member.nick != self.nick {
+                self.completer.add_nick(&member.nick);
+            }
+        }
+    }
+    fn receive_pong(&mut self, _token: u64) {
+        // The web client does not measure latency
     }
     fn change_nick(&mut self, nick: String) {
         self.nick = nick.clone();
         self.messages.push(Message::ChangeNick { nick });
     }
+    fn change_away(&mut self, reason: Option<String>) {
+        match reason {
+            Some(reason) => self.handle_status(format!("You are away: {}", reason)),
+            None => self.handle_status("You are back"),
+        }
+    }
+    fn join_channel(&mut self, channel: String) {
+        self.completer.add_channel(&channel);
+    }
+    fn channel_topic(&mut self, channel: String, topic: Option<Topic>) {
+        self.handle_status(describe_topic(&channel, topic.as_ref()));
+        match topic {
+            Some(topic) => self.topics.insert(channel, topic),
+            None => self.topics.remove(&channel),
+        };
+    }
+    fn show_topic(&mut self, channel: String) {
+        let content = describe_topic(&channel, self.topics.get(&channel));
+        self.handle_status(content);
+    }
+    fn user_list(&mut self, channel: Option<String>, users: Vec<UserInfo>) {
+        self.messages.push(Message::Users { channel, users });
+    }
+    fn channel_list(&mut self, channels: Vec<ChannelInfo>) {
+        self.messages.push(Message::Channels { channels });
+    }
+    fn part_channel(&mut self, channel: String) {
+        self.completer.remove_channel(&channel);
+        self.topics.remove(&channel);
+        self.handle_status(format!("Left {}", channel));
+    }
+    fn current_target(&self) -> Target {
+        // Without buffers, channels and private conversations are reached with /msg
+        Target::Server
+    }
+    fn commands(&self) -> &CommandRegistry {
+        &self.commands
+    }
+    fn commands_mut(&mut self) -> &mut CommandRegistry {
+        &mut self.commands
+    }
+    fn aliases_changed(&mut self) {
+        self.completer.set_commands(self.commands.names());
+        save_aliases(self.commands.aliases());
+    }
+    fn handle_ui_command(
+        &mut self,
+        spec: &'static CommandSpec,
+        params: Vec<String>,
+    ) -> ChatResult<()> {
+        match (spec.name, params.as_slice()) {
+            ("/mute", [name]) => {
+                self.handle_status(format!("Muted notifications from {}", name));
+                self.muted.insert(name.clone());
+                Ok(())
+            }
+            ("/unmute", [name]) => {
+                self.handle_status(format!("Unmuted notifications from {}", name));
+                self.muted.remove(name);
+                Ok(())
+            }
+            _ => Err(ChatError::Unexpected),
+        }
+    }
+    fn show_info(&mut self, content: String) {
+        self.handle_status(content);
+    }
+    fn server_shutdown(&mut self, reason: String, reconnect_after: Option<Duration>) {
+        self.handle_status(format!("Server shutting down: {}", reason));
+        self.disconnect();
+        if let (Some(delay), Some(address)) = (reconnect_after, self.server.clone()) {
+            self.handle_status(format!("Reconnecting in {} seconds", delay.as_secs()));
+            let callback = self.link.callback(move |_| Msg::Connect(address.clone()));
+            self.reconnect = Some(TimeoutService::spawn(delay, callback));
+        }
+    }
     fn quit(&mut self) {
         self.disconnect();
     }
@@ -144,7 +635,7 @@ impl ChatUserInterface for Model {
 impl ChatClient for Model {
     fn connect(&mut self, address: String) -> ChatResult<()> {
         if self.is_connected() {
-            return Err(ChatError::AlreadyConnected.into());
+            return Err(ChatError::AlreadyConnected);
         }
         let cb_recv = self
             .link
@@ -155,9 +646,11 @@ impl ChatClient for Model {
             WebSocketStatus::Opened => Msg::Connected(connecting_to_address.clone()),
         });
         self.ws = Some(
-            WebSocketService::connect_binary(&address, cb_recv, cb_notify.into())
+            WebSocketService::connect_binary(&address, cb_recv, cb_notify)
                 .map_err(|_| ChatError::ConnectionError)?,
         );
+        self.server = Some(address);
+        self.reconnect = None;
         Ok(())
     }
 
@@ -170,9 +663,10 @@ impl ChatClient for Model {
     }
     fn send_binary(&mut self, data: Vec<u8>) -> ChatResult<()> {
         if let Some(ref mut ws) = self.ws {
-            Ok(ws.send_binary(Ok(data)))
+            ws.send_binary(Ok(data));
+            Ok(())
         } else {
-            Err(ChatError::SendError.into())
+            Err(ChatError::SendError)
         }
     }
 }